@@ -0,0 +1,341 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    time::Instant,
+};
+use bevy::prelude::*;
+
+use crate::{
+    chat::{ChatCommand, ChatCommandRegistry, ChatState},
+    terrain::{self, TerrainSettings},
+    voxel::{
+        build_chunk_meshes, chunk_of, world_to_cell, BlockAssets, BlockType, VoxelWorld, CHUNK_EDGE,
+    },
+    MAX_REACH,
+};
+
+
+const RESULTS_PATH: &str = "benchmark_results.toml";
+// Scenes are generated from a fixed seed into a scratch world, so results
+// don't depend on the live world or on the seed it was started with
+const SCENE_SEED: u64 = 0x5eed;
+const RAYCAST_COUNT: usize = 10_000;
+// Chunk columns each way from the origin, enough to cover every ray
+const RAYCAST_RADIUS: i32 = 4;
+const STRESS_SIZE: i32 = 16;
+const TERRAIN_COLUMNS: i32 = 16;
+const WARMUP_FRAMES: usize = 10;
+const MEASURED_FRAMES: usize = 300;
+
+#[derive(Debug, Default, Resource)]
+pub struct Benchmark {
+    // Latest results per scene, written out together so one run doesn't erase another
+    results: BTreeMap<&'static str, Vec<(&'static str, f64)>>,
+    rendering: Option<RenderingRun>,
+}
+
+// The stress cube is meshed from a scratch world and spawned as its own
+// entities, so it never enters VoxelWorld: saves don't capture it, and
+// edits, undo or loading during the run can't collide with its cleanup
+#[derive(Debug, Default)]
+struct RenderingRun {
+    frames: usize,
+    frame_times: Vec<f32>,
+    stress_blocks: usize,
+    stress_entities: Vec<Entity>,
+}
+
+pub fn register_benchmark_commands(mut registry: ResMut<ChatCommandRegistry>) {
+    registry.register(&["benchmark"]);
+}
+
+pub fn benchmark_commands(
+    mut commands: Commands,
+    camera_query: Query<&Transform, With<Camera>>,
+    block_assets: Res<BlockAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut chat_commands: EventReader<ChatCommand>,
+    mut chat: ResMut<ChatState>,
+    mut benchmark: ResMut<Benchmark>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+
+    for command in chat_commands.read() {
+        if command.name != "benchmark" {
+            continue;
+        }
+
+        match command.args.first().map(String::as_str) {
+            Some("raycasting") => {
+                let results = benchmark_raycasting();
+                report(&mut benchmark, &mut chat, "raycasting", results, now);
+            }
+            Some("rendering") if benchmark.rendering.is_some() => {
+                chat.push_system("Rendering benchmark already running", now);
+            }
+            Some("rendering") => {
+                let stress_world = build_stress_world(camera_query.single());
+                benchmark.rendering = Some(RenderingRun {
+                    stress_blocks: stress_world.block_count(),
+                    stress_entities: spawn_stress_meshes(
+                        &mut commands,
+                        &stress_world,
+                        &block_assets,
+                        &mut meshes,
+                    ),
+                    ..default()
+                });
+                chat.push_system(format!("Measuring {MEASURED_FRAMES} frames..."), now);
+            }
+            Some("terrain") => {
+                let results = benchmark_terrain();
+                report(&mut benchmark, &mut chat, "terrain", results, now);
+            }
+            _ => chat.push_system("Usage: /benchmark <raycasting|rendering|terrain>", now),
+        }
+    }
+}
+
+fn scene_settings() -> TerrainSettings {
+    TerrainSettings {
+        seed: SCENE_SEED,
+        ..default()
+    }
+}
+
+fn benchmark_raycasting() -> Vec<(&'static str, f64)> {
+    let settings = scene_settings();
+    let mut world = VoxelWorld::default();
+    for x in -RAYCAST_RADIUS..RAYCAST_RADIUS {
+        for z in -RAYCAST_RADIUS..RAYCAST_RADIUS {
+            for (cell, block) in terrain::generate_chunk(IVec2::new(x, z), &settings) {
+                world.set_block(cell, block);
+            }
+        }
+    }
+
+    // Deterministic spread of rays looking down onto the terrain from just above it
+    let rays = (0..RAYCAST_COUNT)
+        .map(|i| {
+            let t = i as f32 / RAYCAST_COUNT as f32;
+            let angle = i as f32 * 2.399_963; // golden angle
+            let (x, z) = (t * 128.0 - 64.0, angle.sin() * 64.0);
+            let ground = terrain::surface_height(&settings, x as i32, z as i32);
+            let origin = Vec3::new(x, ground as f32 + 6.0, z);
+            let direction = Vec3::new(angle.cos() * 0.5, -1.0, angle.sin() * 0.5);
+            Ray3d::new(origin, Dir3::new(direction).unwrap_or(Dir3::NEG_Y))
+        })
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    let hits = rays
+        .iter()
+        .filter(|ray| world.raycast(ray.origin, *ray.direction, MAX_REACH).is_some())
+        .count();
+    let elapsed = start.elapsed().as_secs_f64();
+
+    vec![
+        ("rays", RAYCAST_COUNT as f64),
+        ("hits", hits as f64),
+        ("blocks", world.block_count() as f64),
+        ("total_ms", elapsed * 1000.0),
+        ("per_ray_us", elapsed * 1_000_000.0 / RAYCAST_COUNT as f64),
+    ]
+}
+
+// Builds the terrain in a scratch world, then meshes every chunk of it
+fn benchmark_terrain() -> Vec<(&'static str, f64)> {
+    let settings = scene_settings();
+    let mut world = VoxelWorld::default();
+
+    let start = Instant::now();
+    for x in 0..TERRAIN_COLUMNS {
+        for z in 0..TERRAIN_COLUMNS {
+            for (cell, block) in terrain::generate_chunk(IVec2::new(x, z), &settings) {
+                world.set_block(cell, block);
+            }
+        }
+    }
+    let generation = start.elapsed().as_secs_f64();
+
+    let layers = settings.max_height.div_euclid(CHUNK_EDGE);
+    let start = Instant::now();
+    let mut vertices = 0;
+    for x in 0..TERRAIN_COLUMNS {
+        for z in 0..TERRAIN_COLUMNS {
+            for y in 0..=layers {
+                vertices += build_chunk_meshes(&world, IVec3::new(x, y, z), None)
+                    .values()
+                    .map(Mesh::count_vertices)
+                    .sum::<usize>();
+            }
+        }
+    }
+    let meshing = start.elapsed().as_secs_f64();
+    let columns = (TERRAIN_COLUMNS * TERRAIN_COLUMNS) as f64;
+
+    vec![
+        ("columns", columns),
+        ("blocks", world.block_count() as f64),
+        ("vertices", vertices as f64),
+        ("generation_ms", generation * 1000.0),
+        ("meshing_ms", meshing * 1000.0),
+        ("total_ms", (generation + meshing) * 1000.0),
+        ("per_column_ms", (generation + meshing) * 1000.0 / columns),
+    ]
+}
+
+// A solid cube of blocks in front of the camera
+fn build_stress_world(camera: &Transform) -> VoxelWorld {
+    let mut world = VoxelWorld::default();
+    let center = camera.translation + *camera.forward() * 24.0;
+    let corner = world_to_cell(center) - IVec3::splat(STRESS_SIZE / 2);
+    for x in 0..STRESS_SIZE {
+        for y in 0..STRESS_SIZE {
+            for z in 0..STRESS_SIZE {
+                world.set_block(corner + IVec3::new(x, y, z), BlockType::Stone);
+            }
+        }
+    }
+    world
+}
+
+// Meshed the same way as the live chunks so the load on the renderer matches
+fn spawn_stress_meshes(
+    commands: &mut Commands,
+    world: &VoxelWorld,
+    block_assets: &BlockAssets,
+    meshes: &mut Assets<Mesh>,
+) -> Vec<Entity> {
+    let chunks = world.blocks().map(|(cell, _)| chunk_of(cell)).collect::<HashSet<_>>();
+    let mut entities = Vec::new();
+    for chunk in chunks {
+        for (block, mesh) in build_chunk_meshes(world, chunk, None) {
+            let entity = commands
+                .spawn((
+                    Name::new("Benchmark chunk"),
+                    Mesh3d(meshes.add(mesh)),
+                    MeshMaterial3d(block_assets.materials[&block].clone()),
+                    Transform::from_translation((chunk * CHUNK_EDGE).as_vec3()),
+                ))
+                .id();
+            entities.push(entity);
+        }
+    }
+    entities
+}
+
+pub fn run_rendering_benchmark(
+    mut commands: Commands,
+    world: Res<VoxelWorld>,
+    mut chat: ResMut<ChatState>,
+    mut benchmark: ResMut<Benchmark>,
+    time: Res<Time>,
+) {
+    let Some(run) = benchmark.rendering.as_mut() else {
+        return;
+    };
+
+    // Skip the frames that absorb the chunk rebuild hitch
+    run.frames += 1;
+    if run.frames > WARMUP_FRAMES {
+        run.frame_times.push(time.delta_secs());
+    }
+    if run.frame_times.len() < MEASURED_FRAMES {
+        return;
+    }
+
+    let fps = run.frame_times.iter().map(|dt| 1.0 / dt.max(f32::EPSILON) as f64);
+    let min = fps.clone().fold(f64::INFINITY, f64::min);
+    let max = fps.clone().fold(0.0, f64::max);
+    let avg = MEASURED_FRAMES as f64 / run.frame_times.iter().map(|dt| *dt as f64).sum::<f64>();
+
+    // Despawning drops the last handles to the meshes, which frees them
+    let blocks = world.block_count() + run.stress_blocks;
+    for entity in run.stress_entities.drain(..) {
+        commands.entity(entity).despawn();
+    }
+
+    let results = vec![
+        ("blocks", blocks as f64),
+        ("frames", MEASURED_FRAMES as f64),
+        ("min_fps", min),
+        ("avg_fps", avg),
+        ("max_fps", max),
+    ];
+    benchmark.rendering = None;
+    report(&mut benchmark, &mut chat, "rendering", results, time.elapsed_secs());
+}
+
+fn report(
+    benchmark: &mut Benchmark,
+    chat: &mut ChatState,
+    scene: &'static str,
+    results: Vec<(&'static str, f64)>,
+    now: f32,
+) {
+    let summary = results
+        .iter()
+        .map(|(key, value)| format!("{key} = {value:.2}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    info!("Benchmark {scene}: {summary}");
+    chat.push_system(format!("{scene}: {summary}"), now);
+
+    benchmark.results.insert(scene, results);
+
+    let mut contents = String::new();
+    for (scene, results) in &benchmark.results {
+        contents.push_str(&format!("[{scene}]\n"));
+        for (key, value) in results {
+            contents.push_str(&format!("{key} = {value}\n"));
+        }
+        contents.push('\n');
+    }
+
+    if let Err(err) = fs::write(RESULTS_PATH, contents) {
+        error!("Failed to write {RESULTS_PATH}: {err}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    #[test]
+    fn rendering_run_leaves_the_live_world_alone() {
+        let mut world = World::new();
+        let mut materials = Assets::<StandardMaterial>::default();
+        world.insert_resource(BlockAssets::new(&mut materials));
+        world.init_resource::<Assets<Mesh>>();
+        world.init_resource::<VoxelWorld>();
+        world.init_resource::<ChatState>();
+        world.init_resource::<Benchmark>();
+        world.init_resource::<Events<ChatCommand>>();
+        world.init_resource::<Time>();
+        // Looking down -Z, so the cube is centred 24 blocks ahead
+        world.spawn(Camera::default());
+        let built = IVec3::new(0, 0, -24);
+        world.resource_mut::<VoxelWorld>().set_block(built, BlockType::Wood);
+        let dirty = world.resource::<VoxelWorld>().dirty_chunk_count();
+
+        world.send_event(ChatCommand {
+            name: "benchmark".to_string(),
+            args: vec!["rendering".to_string()],
+        });
+        world.run_system_once(benchmark_commands).unwrap();
+
+        let voxels = world.resource::<VoxelWorld>();
+        assert_eq!(voxels.blocks().collect::<Vec<_>>(), [(built, BlockType::Wood)]);
+        assert_eq!(voxels.dirty_chunk_count(), dirty);
+
+        let run = world.resource::<Benchmark>().rendering.as_ref().unwrap();
+        assert_eq!(run.stress_blocks, (STRESS_SIZE * STRESS_SIZE * STRESS_SIZE) as usize);
+        let entities = run.stress_entities.clone();
+        assert!(!entities.is_empty());
+        assert!(entities.iter().all(|entity| world.get::<Mesh3d>(*entity).is_some()));
+    }
+}
@@ -0,0 +1,247 @@
+use std::time::{Duration, Instant};
+use bevy::prelude::*;
+
+
+// Overruns are usually one pathological chunk hit over and over, so one
+// warning per system every few seconds is plenty
+const WARNING_INTERVAL: Duration = Duration::from_secs(5);
+
+// World upkeep allowed per frame, split between the budgeted systems in
+// proportion to their priority. Each system only spends its own share, so
+// one that falls behind can't starve the others; what doesn't fit waits in
+// that system's queue for the next frame.
+#[derive(Debug, Resource)]
+pub struct FrameBudget {
+    pub world_work: Duration,
+    systems: Vec<SystemBudget>,
+}
+
+impl Default for FrameBudget {
+    fn default() -> Self {
+        let mut budget = Self {
+            world_work: Duration::from_micros(3000),
+            systems: Vec::new(),
+        };
+        budget.register("meshing", 2);
+        budget.register("terrain", 1);
+        budget
+    }
+}
+
+// One budgeted system and how it spent its last slice
+#[derive(Debug)]
+pub struct SystemBudget {
+    pub name: &'static str,
+    pub priority: u32,
+    pub used: Duration,
+    pub items: usize,
+    // Whether a single item took longer than the whole share
+    pub overran: bool,
+    last_warning: Option<Instant>,
+}
+
+impl FrameBudget {
+    // Systems are kept highest priority first
+    pub fn register(&mut self, name: &'static str, priority: u32) {
+        self.systems.retain(|system| system.name != name);
+        let index = self.systems.partition_point(|system| system.priority >= priority);
+        self.systems.insert(index, SystemBudget {
+            name,
+            priority,
+            used: Duration::ZERO,
+            items: 0,
+            overran: false,
+            last_warning: None,
+        });
+    }
+
+    pub fn share(&self, name: &str) -> Duration {
+        let total = self.systems.iter().map(|system| system.priority).sum::<u32>();
+        self.systems
+            .iter()
+            .find(|system| system.name == name)
+            .map_or(Duration::ZERO, |system| self.world_work * system.priority / total.max(1))
+    }
+
+    pub fn systems(&self) -> &[SystemBudget] {
+        &self.systems
+    }
+
+    pub fn slice(&mut self, name: &'static str) -> WorkSlice<'_> {
+        self.slice_at(name, Instant::now())
+    }
+
+    fn slice_at(&mut self, name: &'static str, now: Instant) -> WorkSlice<'_> {
+        let limit = self.share(name);
+        let Some(system) = self.systems.iter_mut().find(|system| system.name == name) else {
+            panic!("{name} has no frame budget registered");
+        };
+        system.used = Duration::ZERO;
+        system.items = 0;
+        system.overran = false;
+
+        WorkSlice {
+            system,
+            limit,
+            started: now,
+            item_started: now,
+        }
+    }
+}
+
+// One system's share of a frame, spent one work item at a time
+pub struct WorkSlice<'a> {
+    system: &'a mut SystemBudget,
+    limit: Duration,
+    started: Instant,
+    item_started: Instant,
+}
+
+impl WorkSlice<'_> {
+    pub fn has_time(&self) -> bool {
+        self.has_time_at(Instant::now())
+    }
+
+    pub fn item_done(&mut self) {
+        self.item_done_at(Instant::now());
+    }
+
+    // The first item always goes ahead so a tiny budget still makes progress
+    fn has_time_at(&self, now: Instant) -> bool {
+        self.system.items == 0 || now - self.started < self.limit
+    }
+
+    // A single item taking longer than the whole slice points at bad
+    // content rather than too much work, so that's what gets logged
+    fn item_done_at(&mut self, now: Instant) {
+        let item_time = now - self.item_started;
+        self.item_started = now;
+        let system = &mut *self.system;
+        system.items += 1;
+        system.used = now - self.started;
+
+        if item_time <= self.limit {
+            return;
+        }
+
+        system.overran = true;
+        let recently_warned = system
+            .last_warning
+            .is_some_and(|warned| now - warned < WARNING_INTERVAL);
+        if !recently_warned {
+            warn!(
+                "{}: one work item took {:.2} ms, over its {:.2} ms frame budget",
+                system.name,
+                item_time.as_secs_f64() * 1000.0,
+                self.limit.as_secs_f64() * 1000.0,
+            );
+            system.last_warning = Some(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    // Works through `queue` the way the upkeep systems do, each item
+    // taking `item_time` on a fake clock that's moved on by hand instead of
+    // sleeping. Returns how many items ran.
+    fn run(
+        clock: &mut Instant,
+        budget: &mut FrameBudget,
+        name: &'static str,
+        queue: &mut VecDeque<u32>,
+        item_time: Duration,
+    ) -> usize {
+        let mut work = budget.slice_at(name, *clock);
+        let mut done = 0;
+        while !queue.is_empty() && work.has_time_at(*clock) {
+            queue.pop_front();
+            *clock += item_time;
+            work.item_done_at(*clock);
+            done += 1;
+        }
+        done
+    }
+
+    fn system<'a>(budget: &'a FrameBudget, name: &str) -> &'a SystemBudget {
+        budget.systems().iter().find(|system| system.name == name).unwrap()
+    }
+
+    #[test]
+    fn world_work_is_split_by_priority() {
+        let mut budget = FrameBudget::default();
+        assert_eq!(budget.share("meshing"), Duration::from_micros(2000));
+        assert_eq!(budget.share("terrain"), Duration::from_micros(1000));
+
+        budget.register("lighting", 3);
+        assert_eq!(budget.share("lighting"), Duration::from_micros(1500));
+        assert_eq!(budget.share("terrain"), Duration::from_micros(500));
+        let names = budget.systems().iter().map(|system| system.name).collect::<Vec<_>>();
+        assert_eq!(names, ["lighting", "meshing", "terrain"]);
+    }
+
+    #[test]
+    fn slow_items_carry_over_to_the_next_frame() {
+        let mut budget = FrameBudget::default();
+        let mut clock = Instant::now();
+        let slow = budget.share("meshing") * 3;
+        let mut queue = (0..5).collect::<VecDeque<_>>();
+
+        assert_eq!(run(&mut clock, &mut budget, "meshing", &mut queue, slow), 1);
+        assert_eq!(queue, [1, 2, 3, 4]);
+        assert_eq!(run(&mut clock, &mut budget, "meshing", &mut queue, slow), 1);
+        assert_eq!(queue, [2, 3, 4]);
+    }
+
+    #[test]
+    fn a_slow_low_priority_system_does_not_starve_a_higher_one() {
+        let mut budget = FrameBudget::default();
+        let mut clock = Instant::now();
+        let mut terrain = (0..5).collect::<VecDeque<_>>();
+        let mut meshing = (0..50).collect::<VecDeque<_>>();
+
+        // Terrain runs first in the frame and its one item overruns badly
+        let slow = budget.share("terrain") * 3;
+        assert_eq!(run(&mut clock, &mut budget, "terrain", &mut terrain, slow), 1);
+        let fast = Duration::from_micros(100);
+        assert_eq!(run(&mut clock, &mut budget, "meshing", &mut meshing, fast), 20);
+        assert_eq!(terrain.len(), 4);
+    }
+
+    #[test]
+    fn fast_items_all_fit_in_the_slice() {
+        let mut budget = FrameBudget::default();
+        let mut clock = Instant::now();
+        let mut queue = (0..10).collect::<VecDeque<_>>();
+
+        let fast = Duration::from_micros(10);
+        assert_eq!(run(&mut clock, &mut budget, "terrain", &mut queue, fast), 10);
+        assert!(queue.is_empty());
+        let terrain = system(&budget, "terrain");
+        assert_eq!(terrain.items, 10);
+        assert_eq!(terrain.used, Duration::from_micros(100));
+        assert!(!terrain.overran);
+    }
+
+    #[test]
+    fn items_over_the_whole_slice_are_warned_about() {
+        let mut budget = FrameBudget::default();
+        let mut clock = Instant::now();
+        let mut queue = (0..3).collect::<VecDeque<_>>();
+        let slow = budget.share("meshing") * 3;
+
+        run(&mut clock, &mut budget, "meshing", &mut queue, slow);
+        let warned = system(&budget, "meshing").last_warning;
+        assert_eq!(warned, Some(clock));
+        assert!(system(&budget, "meshing").overran);
+        assert!(system(&budget, "terrain").last_warning.is_none());
+
+        // Rate limited: another overrun right after doesn't warn again
+        run(&mut clock, &mut budget, "meshing", &mut queue, slow);
+        assert_eq!(system(&budget, "meshing").last_warning, warned);
+    }
+}
@@ -0,0 +1,377 @@
+use bevy::{
+    input::{
+        keyboard::{Key, KeyboardInput},
+        mouse::MouseWheel,
+        ButtonState,
+    },
+    prelude::*,
+};
+
+use crate::{
+    cursor::GrabState,
+    hotbar::{Hotbar, SelectedBlock},
+    voxel::BlockType,
+};
+
+
+// Entries per page, picked with the number keys
+const PAGE_SIZE: usize = 9;
+const SWATCH_SIZE: f32 = 24.0;
+
+#[derive(Debug, Resource)]
+pub struct CatalogSettings {
+    pub toggle_key: KeyCode,
+}
+
+impl Default for CatalogSettings {
+    fn default() -> Self {
+        Self {
+            toggle_key: KeyCode::KeyB,
+        }
+    }
+}
+
+// Every block type, filtered by typing part of a name. Picking one puts
+// it in the current hotbar slot.
+#[derive(Debug, Resource)]
+pub struct Catalog {
+    pub open: bool,
+    pub search: String,
+    pub page: usize,
+    // Closing puts the cursor back the way it was when the catalog opened
+    pub was_grabbed: bool,
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Self {
+            open: false,
+            search: String::new(),
+            page: 0,
+            was_grabbed: true,
+        }
+    }
+}
+
+impl Catalog {
+    pub fn matches(&self) -> Vec<BlockType> {
+        let search = self.search.to_lowercase();
+        BlockType::ALL
+            .into_iter()
+            .filter(|block| block.name().contains(&search))
+            .collect()
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.matches().len().div_ceil(PAGE_SIZE).max(1)
+    }
+
+    pub fn page_entries(&self) -> Vec<BlockType> {
+        self.matches().into_iter().skip(self.page * PAGE_SIZE).take(PAGE_SIZE).collect()
+    }
+}
+
+#[derive(Component)]
+pub struct CatalogRoot;
+
+#[derive(Component)]
+pub struct CatalogTitle;
+
+// Position on the current page, 0 being the entry picked with 1
+#[derive(Component)]
+pub struct CatalogSwatch(usize);
+
+#[derive(Component)]
+pub struct CatalogEntry(usize);
+
+// Run condition for gameplay input that must not fire while searching
+pub fn is_closed(catalog: Res<Catalog>) -> bool {
+    !catalog.open
+}
+
+pub fn setup_catalog(mut commands: Commands) {
+    commands
+        .spawn((
+            Name::new("Catalog"),
+            CatalogRoot,
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Percent(35.0),
+                top: Val::Percent(20.0),
+                width: Val::Percent(30.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(10.0)),
+                row_gap: Val::Px(4.0),
+                ..default()
+            },
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.7)),
+            Visibility::Hidden,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                CatalogTitle,
+                Text::default(),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ));
+            for index in 0..PAGE_SIZE {
+                parent
+                    .spawn(Node {
+                        column_gap: Val::Px(8.0),
+                        align_items: AlignItems::Center,
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        // Same color swatch the hotbar slots use
+                        row.spawn((
+                            CatalogSwatch(index),
+                            Node {
+                                width: Val::Px(SWATCH_SIZE),
+                                height: Val::Px(SWATCH_SIZE),
+                                ..default()
+                            },
+                            BackgroundColor(Color::NONE),
+                        ));
+                        row.spawn((
+                            CatalogEntry(index),
+                            Text::default(),
+                            TextFont {
+                                font_size: 16.0,
+                                ..default()
+                            },
+                            TextColor(Color::WHITE),
+                        ));
+                    });
+            }
+        });
+}
+
+pub fn catalog_input(
+    settings: Res<CatalogSettings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut keyboard_events: EventReader<KeyboardInput>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    mut grab_state: ResMut<GrabState>,
+    mut catalog: ResMut<Catalog>,
+    mut hotbar: ResMut<Hotbar>,
+    mut selected: ResMut<SelectedBlock>,
+) {
+    if !catalog.open {
+        if keyboard.just_pressed(settings.toggle_key) {
+            catalog.open = true;
+            catalog.search.clear();
+            catalog.page = 0;
+            catalog.was_grabbed = grab_state.grabbed;
+            grab_state.grabbed = false;
+        }
+        // Don't let the key that opened the catalog end up in the search
+        keyboard_events.clear();
+        mouse_wheel.clear();
+        return;
+    }
+
+    for event in mouse_wheel.read() {
+        if event.y < 0.0 {
+            catalog.page = (catalog.page + 1).min(catalog.page_count() - 1);
+        } else if event.y > 0.0 {
+            catalog.page = catalog.page.saturating_sub(1);
+        }
+    }
+
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+
+        let pick = match &event.logical_key {
+            Key::Escape => None,
+            // Enter takes the first entry on the page
+            Key::Enter => Some(0),
+            Key::Character(text) => match text.parse::<usize>() {
+                Ok(number @ 1..=PAGE_SIZE) => Some(number - 1),
+                Ok(_) => continue,
+                Err(_) => {
+                    catalog.search.extend(text.chars().filter(|c| !c.is_control()));
+                    catalog.page = 0;
+                    continue;
+                }
+            },
+            Key::Backspace => {
+                catalog.search.pop();
+                catalog.page = 0;
+                continue;
+            }
+            Key::PageDown | Key::ArrowRight => {
+                catalog.page = (catalog.page + 1).min(catalog.page_count() - 1);
+                continue;
+            }
+            Key::PageUp | Key::ArrowLeft => {
+                catalog.page = catalog.page.saturating_sub(1);
+                continue;
+            }
+            _ => continue,
+        };
+
+        // A number past the end of the page is ignored rather than closing
+        let block = pick.and_then(|index| catalog.page_entries().get(index).copied());
+        if pick.is_some() && block.is_none() {
+            continue;
+        }
+        if let Some(block) = block {
+            hotbar.assign(block);
+            selected.0 = block;
+        }
+        catalog.open = false;
+        grab_state.grabbed = catalog.was_grabbed;
+        break;
+    }
+}
+
+pub fn update_catalog_ui(
+    catalog: Res<Catalog>,
+    mut root_query: Query<&mut Visibility, With<CatalogRoot>>,
+    mut title_query: Query<&mut Text, (With<CatalogTitle>, Without<CatalogEntry>)>,
+    mut swatch_query: Query<(&CatalogSwatch, &mut BackgroundColor)>,
+    mut entry_query: Query<(&CatalogEntry, &mut Text), Without<CatalogTitle>>,
+) {
+    if !catalog.is_changed() {
+        return;
+    }
+
+    *root_query.single_mut() = if catalog.open {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    if !catalog.open {
+        return;
+    }
+
+    title_query.single_mut().0 = format!(
+        "Search: {}_   page {}/{}",
+        catalog.search,
+        catalog.page + 1,
+        catalog.page_count()
+    );
+
+    let entries = catalog.page_entries();
+    for (swatch, mut background) in &mut swatch_query {
+        background.0 = entries.get(swatch.0).map_or(Color::NONE, |block| block.color());
+    }
+    for (entry, mut text) in &mut entry_query {
+        text.0 = match entries.get(entry.0) {
+            Some(block) => format!("{}  {}", entry.0 + 1, block.name()),
+            None => String::new(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{ecs::system::RunSystemOnce, input::keyboard::NativeKeyCode};
+
+    use super::*;
+
+    fn catalog_world() -> World {
+        let mut world = World::new();
+        world.init_resource::<CatalogSettings>();
+        world.init_resource::<Catalog>();
+        world.init_resource::<GrabState>();
+        world.init_resource::<Hotbar>();
+        world.init_resource::<SelectedBlock>();
+        world.init_resource::<Events<KeyboardInput>>();
+        world.init_resource::<Events<MouseWheel>>();
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world
+    }
+
+    fn key(world: &mut World, logical_key: Key) {
+        world.send_event(KeyboardInput {
+            key_code: KeyCode::Unidentified(NativeKeyCode::Unidentified),
+            logical_key,
+            state: ButtonState::Pressed,
+            repeat: false,
+            window: Entity::PLACEHOLDER,
+        });
+    }
+
+    fn type_text(world: &mut World, text: &str) {
+        for c in text.chars() {
+            key(world, Key::Character(c.to_string().into()));
+        }
+    }
+
+    // One frame of catalog input; each run reads events afresh, so they're
+    // dropped afterwards rather than seen again next frame
+    fn run(world: &mut World) {
+        world.run_system_once(catalog_input).unwrap();
+        world.resource_mut::<Events<KeyboardInput>>().clear();
+        world.resource_mut::<ButtonInput<KeyCode>>().reset_all();
+    }
+
+    fn open(world: &mut World) {
+        world.resource_mut::<ButtonInput<KeyCode>>().press(KeyCode::KeyB);
+        run(world);
+        assert!(world.resource::<Catalog>().open);
+    }
+
+    #[test]
+    fn search_filters_by_name() {
+        let mut catalog = Catalog::default();
+        assert_eq!(catalog.matches(), BlockType::ALL);
+
+        catalog.search = "Stone".to_string();
+        assert_eq!(catalog.matches(), [BlockType::Stone, BlockType::Sandstone]);
+        catalog.search = "xyz".to_string();
+        assert!(catalog.matches().is_empty());
+        assert_eq!(catalog.page_count(), 1);
+    }
+
+    #[test]
+    fn picking_fills_the_current_slot() {
+        let mut world = catalog_world();
+        world.resource_mut::<Hotbar>().current = 2;
+
+        open(&mut world);
+        type_text(&mut world, "ss");
+        key(&mut world, Key::Character("2".into()));
+        run(&mut world);
+
+        // "ss" matches grass and glass, in catalog order
+        assert!(!world.resource::<Catalog>().open);
+        assert_eq!(world.resource::<Hotbar>().slots[2], BlockType::Glass);
+        assert_eq!(world.resource::<SelectedBlock>().0, BlockType::Glass);
+        assert!(world.resource::<GrabState>().grabbed);
+    }
+
+    #[test]
+    fn numbers_past_the_page_are_ignored() {
+        let mut world = catalog_world();
+        open(&mut world);
+        type_text(&mut world, "wood9");
+        run(&mut world);
+
+        let catalog = world.resource::<Catalog>();
+        assert!(catalog.open);
+        assert_eq!(catalog.search, "wood");
+        assert_eq!(world.resource::<Hotbar>().slots, BlockType::ALL);
+    }
+
+    #[test]
+    fn escape_changes_nothing_and_restores_the_cursor() {
+        let mut world = catalog_world();
+        world.resource_mut::<GrabState>().grabbed = false;
+        open(&mut world);
+        type_text(&mut world, "glass");
+        key(&mut world, Key::Escape);
+        run(&mut world);
+
+        assert!(!world.resource::<Catalog>().open);
+        assert_eq!(world.resource::<Hotbar>().slots, BlockType::ALL);
+        assert_eq!(world.resource::<SelectedBlock>().0, BlockType::Stone);
+        assert!(!world.resource::<GrabState>().grabbed);
+    }
+}
@@ -0,0 +1,444 @@
+use std::collections::HashSet;
+use bevy::{
+    input::{
+        keyboard::{Key, KeyboardInput},
+        mouse::MouseWheel,
+        ButtonState,
+    },
+    prelude::*,
+};
+
+use crate::{cursor::GrabState, history::BoundedHistory};
+
+
+const MAX_MESSAGES: usize = 100;
+// Keeps a flood of long messages from growing the log without bound
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+const VISIBLE_MESSAGES: usize = 10;
+const FADE_DELAY: f32 = 5.0;
+const FADE_DURATION: f32 = 1.0;
+
+#[derive(Debug, Resource)]
+pub struct ChatSettings {
+    pub open_key: KeyCode,
+}
+
+impl Default for ChatSettings {
+    fn default() -> Self {
+        Self {
+            open_key: KeyCode::KeyT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatChannel {
+    All,
+    System,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub channel: ChatChannel,
+    pub text: String,
+}
+
+#[derive(Debug, Resource)]
+pub struct ChatState {
+    pub open: bool,
+    pub messages: BoundedHistory<ChatMessage>,
+    pub input: String,
+    // How many messages the panel is scrolled back from the newest one
+    pub scroll: usize,
+    pub last_activity: f32,
+    // Closing puts the cursor back the way it was when the chat opened
+    pub was_grabbed: bool,
+}
+
+impl Default for ChatState {
+    fn default() -> Self {
+        Self {
+            open: false,
+            messages: BoundedHistory::new(MAX_MESSAGES)
+                .with_byte_budget(MAX_MESSAGE_BYTES, |message| message.text.len()),
+            input: String::new(),
+            scroll: 0,
+            last_activity: 0.0,
+            was_grabbed: true,
+        }
+    }
+}
+
+impl ChatState {
+    pub fn push(&mut self, channel: ChatChannel, text: impl Into<String>, now: f32) {
+        self.messages.push(ChatMessage { channel, text: text.into() });
+        self.scroll = 0;
+        self.last_activity = now;
+    }
+
+    pub fn push_system(&mut self, text: impl Into<String>, now: f32) {
+        self.push(ChatChannel::System, text, now);
+    }
+}
+
+// A `/command` typed into chat whose name some other system registered
+#[derive(Debug, Clone, Event)]
+pub struct ChatCommand {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+// Names handled outside the chat; anything else gets an "Unknown command" reply
+#[derive(Debug, Default, Resource)]
+pub struct ChatCommandRegistry {
+    names: HashSet<&'static str>,
+}
+
+impl ChatCommandRegistry {
+    pub fn register(&mut self, names: &[&'static str]) {
+        self.names.extend(names);
+    }
+}
+
+#[derive(Component)]
+pub struct ChatLog;
+
+#[derive(Component)]
+pub struct ChatInput;
+
+// Run condition for gameplay input that must not fire while typing
+pub fn is_closed(chat: Res<ChatState>) -> bool {
+    !chat.open
+}
+
+pub fn setup_chat(mut commands: Commands) {
+    commands
+        .spawn((
+            Name::new("Chat"),
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                width: Val::Px(480.0),
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                ChatLog,
+                Text::default(),
+                TextFont {
+                    font_size: 16.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ));
+            parent.spawn((
+                ChatInput,
+                Text::default(),
+                TextFont {
+                    font_size: 16.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+                BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.5)),
+                Visibility::Hidden,
+            ));
+        });
+}
+
+pub fn chat_input(
+    settings: Res<ChatSettings>,
+    mut grab_state: ResMut<GrabState>,
+    mut chat: ResMut<ChatState>,
+    mut keyboard_events: EventReader<KeyboardInput>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    mut chat_commands: EventWriter<ChatCommand>,
+    registry: Res<ChatCommandRegistry>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+
+    if !chat.open {
+        if keyboard.just_pressed(settings.open_key) {
+            chat.open = true;
+            chat.last_activity = now;
+            chat.was_grabbed = grab_state.grabbed;
+            grab_state.grabbed = false;
+        }
+        // Don't let the key that opened the chat end up in the input
+        keyboard_events.clear();
+        mouse_wheel.clear();
+        return;
+    }
+
+    for event in mouse_wheel.read() {
+        if event.y > 0.0 {
+            let max_scroll = chat.messages.len().saturating_sub(VISIBLE_MESSAGES);
+            chat.scroll = (chat.scroll + 1).min(max_scroll);
+        } else if event.y < 0.0 {
+            chat.scroll = chat.scroll.saturating_sub(1);
+        }
+    }
+
+    for event in keyboard_events.read() {
+        if event.state != ButtonState::Pressed {
+            continue;
+        }
+
+        match &event.logical_key {
+            Key::Enter => {
+                let input = std::mem::take(&mut chat.input);
+                send_message(&mut chat, &registry, &mut chat_commands, input.trim(), now);
+                close_chat(&mut chat, &mut grab_state, now);
+                break;
+            }
+            Key::Escape => {
+                chat.input.clear();
+                close_chat(&mut chat, &mut grab_state, now);
+                break;
+            }
+            Key::Backspace => {
+                chat.input.pop();
+            }
+            Key::Space => chat.input.push(' '),
+            Key::Character(text) => {
+                chat.input.extend(text.chars().filter(|c| !c.is_control()));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn send_message(
+    chat: &mut ChatState,
+    registry: &ChatCommandRegistry,
+    chat_commands: &mut EventWriter<ChatCommand>,
+    input: &str,
+    now: f32,
+) {
+    if input.is_empty() {
+        return;
+    }
+
+    // Single-player: team and whisper channels have nobody to deliver to,
+    // so they only ever produce system replies
+    if let Some(command) = input.strip_prefix('/') {
+        let mut parts = command.split_whitespace();
+        let name = parts.next();
+        let args = parts.collect::<Vec<_>>();
+        match (name, args.as_slice()) {
+            (Some("t"), [_, ..]) => {
+                chat.push_system("There are no teammates to receive team chat", now)
+            }
+            (Some("t"), []) => chat.push_system("Usage: /t <message>", now),
+            (Some("w"), [player, _, ..]) => {
+                chat.push_system(format!("Player '{player}' is not online"), now)
+            }
+            (Some("w"), _) => chat.push_system("Usage: /w <player> <message>", now),
+            (Some(name), args) if registry.names.contains(name) => {
+                chat_commands.send(ChatCommand {
+                    name: name.to_string(),
+                    args: args.iter().map(|arg| arg.to_string()).collect(),
+                });
+            }
+            (Some(name), _) => chat.push_system(format!("Unknown command: /{name}"), now),
+            (None, _) => {}
+        }
+    } else {
+        chat.push(ChatChannel::All, input, now);
+    }
+}
+
+fn close_chat(chat: &mut ChatState, grab_state: &mut GrabState, now: f32) {
+    chat.open = false;
+    chat.scroll = 0;
+    chat.last_activity = now;
+    grab_state.grabbed = chat.was_grabbed;
+}
+
+pub fn update_chat_ui(
+    chat: Res<ChatState>,
+    mut log_query: Query<(&mut Text, &mut TextColor), (With<ChatLog>, Without<ChatInput>)>,
+    mut input_query: Query<(&mut Text, &mut Visibility), (With<ChatInput>, Without<ChatLog>)>,
+    time: Res<Time>,
+) {
+    let (mut log_text, mut log_color) = log_query.single_mut();
+    let (mut input_text, mut input_visibility) = input_query.single_mut();
+
+    let end = chat.messages.len().saturating_sub(chat.scroll);
+    let start = end.saturating_sub(VISIBLE_MESSAGES);
+    log_text.0 = chat
+        .messages
+        .iter()
+        .skip(start)
+        .take(end - start)
+        .map(|message| match message.channel {
+            ChatChannel::All => message.text.clone(),
+            ChatChannel::System => format!("* {}", message.text),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let alpha = if chat.open {
+        1.0
+    } else {
+        let idle = time.elapsed_secs() - chat.last_activity;
+        1.0 - ((idle - FADE_DELAY) / FADE_DURATION).clamp(0.0, 1.0)
+    };
+    log_color.0 = Color::WHITE.with_alpha(alpha);
+
+    input_text.0 = format!("> {}", chat.input);
+    *input_visibility = if chat.open {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{ecs::system::RunSystemOnce, input::keyboard::NativeKeyCode};
+
+    use super::*;
+
+    fn chat_world() -> World {
+        let mut world = World::new();
+        world.init_resource::<ChatSettings>();
+        world.init_resource::<GrabState>();
+        world.init_resource::<ChatState>();
+        world.init_resource::<Events<KeyboardInput>>();
+        world.init_resource::<Events<MouseWheel>>();
+        world.init_resource::<Events<ChatCommand>>();
+        world.init_resource::<ChatCommandRegistry>();
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<Time>();
+        world
+    }
+
+    fn key(world: &mut World, logical_key: Key) {
+        world.send_event(KeyboardInput {
+            key_code: KeyCode::Unidentified(NativeKeyCode::Unidentified),
+            logical_key,
+            state: ButtonState::Pressed,
+            repeat: false,
+            window: Entity::PLACEHOLDER,
+        });
+    }
+
+    // One frame of chat input; each run reads events afresh, so they're
+    // dropped afterwards rather than seen again next frame
+    fn run(world: &mut World) {
+        world.run_system_once(chat_input).unwrap();
+        world.resource_mut::<Events<KeyboardInput>>().clear();
+        world.resource_mut::<ButtonInput<KeyCode>>().reset_all();
+    }
+
+    fn open(world: &mut World) {
+        world.resource_mut::<ButtonInput<KeyCode>>().press(KeyCode::KeyT);
+        run(world);
+        assert!(world.resource::<ChatState>().open);
+    }
+
+    // Opens the chat, types `input` and presses enter
+    fn send(world: &mut World, input: &str) {
+        open(world);
+        for c in input.chars() {
+            match c {
+                ' ' => key(world, Key::Space),
+                c => key(world, Key::Character(c.to_string().into())),
+            }
+        }
+        key(world, Key::Enter);
+        run(world);
+        assert!(!world.resource::<ChatState>().open);
+    }
+
+    fn messages(world: &World) -> Vec<(ChatChannel, String)> {
+        let chat = world.resource::<ChatState>();
+        chat.messages.iter().map(|message| (message.channel, message.text.clone())).collect()
+    }
+
+    fn commands(world: &World) -> Vec<ChatCommand> {
+        let events = world.resource::<Events<ChatCommand>>();
+        events.get_cursor().read(events).cloned().collect()
+    }
+
+    fn system(text: &str) -> Vec<(ChatChannel, String)> {
+        vec![(ChatChannel::System, text.to_string())]
+    }
+
+    #[test]
+    fn plain_messages_go_to_everyone() {
+        let mut world = chat_world();
+        send(&mut world, "  hello there ");
+        assert_eq!(messages(&world), vec![(ChatChannel::All, "hello there".to_string())]);
+    }
+
+    #[test]
+    fn empty_and_blank_messages_are_dropped() {
+        let mut world = chat_world();
+        send(&mut world, "");
+        send(&mut world, "   ");
+        assert!(messages(&world).is_empty());
+    }
+
+    #[test]
+    fn team_chat_has_nobody_to_reach() {
+        let mut world = chat_world();
+        send(&mut world, "/t rush left");
+        assert_eq!(messages(&world), system("There are no teammates to receive team chat"));
+
+        let mut world = chat_world();
+        send(&mut world, "/t");
+        assert_eq!(messages(&world), system("Usage: /t <message>"));
+    }
+
+    #[test]
+    fn whispers_name_the_missing_player() {
+        let mut world = chat_world();
+        send(&mut world, "/w bob psst");
+        assert_eq!(messages(&world), system("Player 'bob' is not online"));
+
+        let mut world = chat_world();
+        send(&mut world, "/w bob");
+        assert_eq!(messages(&world), system("Usage: /w <player> <message>"));
+    }
+
+    #[test]
+    fn registered_commands_are_dispatched() {
+        let mut world = chat_world();
+        world.resource_mut::<ChatCommandRegistry>().register(&["goto"]);
+        send(&mut world, "/goto  castle gate");
+
+        let commands = commands(&world);
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].name, "goto");
+        assert_eq!(commands[0].args, ["castle", "gate"]);
+        assert!(messages(&world).is_empty());
+    }
+
+    #[test]
+    fn unregistered_commands_are_reported() {
+        let mut world = chat_world();
+        send(&mut world, "/dance");
+        assert_eq!(messages(&world), system("Unknown command: /dance"));
+        assert!(commands(&world).is_empty());
+    }
+
+    #[test]
+    fn closing_restores_the_cursor_grab() {
+        let mut world = chat_world();
+        send(&mut world, "hi");
+        assert!(world.resource::<GrabState>().grabbed);
+
+        // Opened with the cursor already released, e.g. after escape
+        world.resource_mut::<GrabState>().grabbed = false;
+        open(&mut world);
+        key(&mut world, Key::Escape);
+        run(&mut world);
+        assert!(!world.resource::<GrabState>().grabbed);
+    }
+}
@@ -0,0 +1,306 @@
+use bevy::prelude::*;
+
+use crate::voxel::{world_to_cell, VoxelWorld};
+
+
+// How much lower the box and eye are while crouching
+const CROUCH_DROP: f32 = 0.3;
+// Gap left between the box and a block it was pushed against
+const SKIN: f32 = 0.001;
+// Longest distance moved along an axis before checking for blocks again
+const MAX_STEP: f32 = 0.5;
+
+// The player's box, with the camera at `eye_height` above its bottom.
+// Everything that collides with or keeps clear of the player reads this
+// from the camera, so gameplay can resize it.
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct CollisionShape {
+    pub width: f32,
+    pub height: f32,
+    pub eye_height: f32,
+}
+
+impl Default for CollisionShape {
+    fn default() -> Self {
+        Self {
+            width: 0.6,
+            height: 1.8,
+            eye_height: 1.6,
+        }
+    }
+}
+
+impl CollisionShape {
+    pub fn crouched(self) -> Self {
+        Self {
+            height: self.height - CROUCH_DROP,
+            eye_height: self.eye_height - CROUCH_DROP,
+            ..self
+        }
+    }
+}
+
+fn player_bounds(shape: CollisionShape, eye: Vec3) -> (Vec3, Vec3) {
+    let half_width = shape.width * 0.5;
+    (
+        eye - Vec3::new(half_width, shape.eye_height, half_width),
+        eye + Vec3::new(half_width, shape.height - shape.eye_height, half_width),
+    )
+}
+
+// Vertical state for walking mode, kept between frames
+#[derive(Debug, Default, Resource)]
+pub struct PlayerPhysics {
+    pub vertical_speed: f32,
+    pub grounded: bool,
+    // The shape to stand back up into while crouching
+    pub standing: Option<CollisionShape>,
+}
+
+pub fn player_overlaps(shape: CollisionShape, eye: Vec3, cell: IVec3) -> bool {
+    let (min, max) = player_bounds(shape, eye);
+    let (cell_min, cell_max) = (cell.as_vec3(), cell.as_vec3() + Vec3::ONE);
+    min.cmplt(cell_max).all() && max.cmpgt(cell_min).all()
+}
+
+// Whether any block intersects the player box, e.g. after a teleport
+pub fn player_obstructed(world: &VoxelWorld, shape: CollisionShape, eye: Vec3) -> bool {
+    let (min, max) = player_bounds(shape, eye);
+    cells_between(world_to_cell(min), world_to_cell(max))
+        .any(|cell| world.get_block(cell).is_some() && player_overlaps(shape, eye, cell))
+}
+
+// Where the eye ends up after switching from `from` to `to` with the feet
+// kept in place, or None if the new box wouldn't fit there, e.g. standing
+// up under a low ceiling
+pub fn resize(
+    world: &VoxelWorld,
+    eye: Vec3,
+    from: CollisionShape,
+    to: CollisionShape,
+) -> Option<Vec3> {
+    let eye = eye + Vec3::Y * (to.eye_height - from.eye_height);
+    (!player_obstructed(world, to, eye)).then_some(eye)
+}
+
+// Whether any block sits directly under the player's feet
+pub fn has_support(world: &VoxelWorld, shape: CollisionShape, eye: Vec3) -> bool {
+    let (min, max) = player_bounds(shape, eye);
+    let below = min.y - 2.0 * SKIN;
+    let from = world_to_cell(Vec3::new(min.x, below, min.z));
+    let to = world_to_cell(Vec3::new(max.x, below, max.z));
+    cells_between(from, to).any(|cell| world.get_block(cell).is_some())
+}
+
+// Resolves one axis at a time so pushing diagonally into a wall still
+// slides along it. Cells the box already overlapped before moving are
+// ignored, so a player stuck inside a block can always walk out.
+// Also returns which axes were stopped by a block.
+pub fn move_and_slide(
+    world: &VoxelWorld,
+    shape: CollisionShape,
+    eye: Vec3,
+    motion: Vec3,
+) -> (Vec3, BVec3) {
+    // Long moves are split up so a fast fall can't skip over a thin floor
+    let steps = (motion.abs().max_element() / MAX_STEP).ceil().max(1.0);
+    let step = motion / steps;
+    let mut position = eye;
+    let mut blocked = BVec3::FALSE;
+
+    for _ in 0..steps as usize {
+        for axis in [1, 0, 2] {
+            if step[axis] == 0.0 || blocked.test(axis) {
+                continue;
+            }
+
+            let (old_min, old_max) = player_bounds(shape, position);
+            let (old_from, old_to) = (world_to_cell(old_min), world_to_cell(old_max));
+            position[axis] += step[axis];
+            let (min, max) = player_bounds(shape, position);
+
+            let blocking = cells_between(world_to_cell(min), world_to_cell(max))
+                .filter(|cell| !(cell.cmpge(old_from).all() && cell.cmple(old_to).all()))
+                .filter(|cell| world.get_block(*cell).is_some())
+                .map(|cell| cell[axis]);
+
+            if step[axis] > 0.0 {
+                if let Some(nearest) = blocking.min() {
+                    position[axis] -= max[axis] - (nearest as f32 - SKIN);
+                    blocked.set(axis, true);
+                }
+            } else if let Some(nearest) = blocking.max() {
+                position[axis] += (nearest as f32 + 1.0 + SKIN) - min[axis];
+                blocked.set(axis, true);
+            }
+        }
+    }
+
+    (position, blocked)
+}
+
+fn cells_between(from: IVec3, to: IVec3) -> impl Iterator<Item = IVec3> {
+    (from.x..=to.x).flat_map(move |x| {
+        (from.y..=to.y).flat_map(move |y| (from.z..=to.z).map(move |z| IVec3::new(x, y, z)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::BlockType;
+
+    const EYE: Vec3 = Vec3::new(1.0, 2.0, 0.5);
+    // Where the default box stops against a block face at 2
+    const STOP: f32 = 2.0 - 0.3 - SKIN;
+
+    fn move_and_slide(world: &VoxelWorld, eye: Vec3, motion: Vec3) -> (Vec3, BVec3) {
+        super::move_and_slide(world, CollisionShape::default(), eye, motion)
+    }
+
+    fn world_with(cells: impl IntoIterator<Item = IVec3>) -> VoxelWorld {
+        let mut world = VoxelWorld::default();
+        for cell in cells {
+            world.set_block(cell, BlockType::Stone);
+        }
+        world
+    }
+
+    // Three blocks high and ten wide, across x or z
+    fn wall_x(x: i32) -> impl Iterator<Item = IVec3> {
+        (-5..5).flat_map(move |z| (0..3).map(move |y| IVec3::new(x, y, z)))
+    }
+
+    fn wall_z(z: i32) -> impl Iterator<Item = IVec3> {
+        (-5..5).flat_map(move |x| (0..3).map(move |y| IVec3::new(x, y, z)))
+    }
+
+    fn assert_near(actual: Vec3, expected: Vec3) {
+        assert!(actual.abs_diff_eq(expected, 1e-4), "{actual} != {expected}");
+    }
+
+    #[test]
+    fn moves_freely_through_empty_space() {
+        let motion = Vec3::new(3.0, -2.0, 1.5);
+        let (position, blocked) = move_and_slide(&VoxelWorld::default(), EYE, motion);
+        assert_near(position, EYE + motion);
+        assert_eq!(blocked, BVec3::FALSE);
+    }
+
+    #[test]
+    fn slides_along_a_wall_when_moving_diagonally_into_it() {
+        let world = world_with(wall_x(2));
+        let (position, blocked) = move_and_slide(&world, EYE, Vec3::new(1.0, 0.0, 1.0));
+        assert_near(position, Vec3::new(STOP, 2.0, 1.5));
+        assert_eq!(blocked, BVec3::new(true, false, false));
+    }
+
+    #[test]
+    fn stops_in_an_inside_corner() {
+        let world = world_with(wall_x(2).chain(wall_z(2)));
+        let start = Vec3::new(1.0, 2.0, 1.0);
+        let (position, blocked) = move_and_slide(&world, start, Vec3::new(1.0, 0.0, 1.0));
+        assert_near(position, Vec3::new(STOP, 2.0, STOP));
+        assert_eq!(blocked, BVec3::new(true, false, true));
+    }
+
+    #[test]
+    fn slides_around_an_outside_corner() {
+        // A pillar diagonally ahead catches the box on one axis only
+        let world = world_with((0..3).map(|y| IVec3::new(2, y, 2)));
+        let start = Vec3::new(1.0, 2.0, 1.0);
+        let (position, blocked) = move_and_slide(&world, start, Vec3::new(1.0, 0.0, 1.0));
+        assert_near(position, Vec3::new(2.0, 2.0, STOP));
+        assert_eq!(blocked, BVec3::new(false, false, true));
+    }
+
+    #[test]
+    fn fast_fall_lands_on_a_thin_floor() {
+        let world = world_with((-2..2).flat_map(|x| (-2..2).map(move |z| IVec3::new(x, 0, z))));
+        let start = Vec3::new(0.5, 10.0, 0.5);
+        let (position, blocked) = move_and_slide(&world, start, Vec3::NEG_Y * 20.0);
+        let eye_height = CollisionShape::default().eye_height;
+        assert_near(position, Vec3::new(0.5, 1.0 + eye_height + SKIN, 0.5));
+        assert_eq!(blocked, BVec3::new(false, true, false));
+        assert!(has_support(&world, CollisionShape::default(), position));
+    }
+
+    #[test]
+    fn walks_out_of_a_block_it_is_stuck_in() {
+        let world = world_with([IVec3::new(1, 1, 0)]);
+        let (position, blocked) = move_and_slide(&world, EYE, Vec3::NEG_X);
+        assert_near(position, EYE - Vec3::X);
+        assert_eq!(blocked, BVec3::FALSE);
+    }
+
+    #[test]
+    fn player_box_overlaps_cells_it_reaches_into() {
+        let eye = Vec3::new(0.5, 1.6, 0.5);
+        let overlaps = |cell| player_overlaps(CollisionShape::default(), eye, cell);
+        assert!(overlaps(IVec3::ZERO));
+        assert!(overlaps(IVec3::Y));
+        assert!(!overlaps(IVec3::NEG_Y));
+        assert!(!overlaps(IVec3::X));
+        assert!(!overlaps(IVec3::new(0, 2, 0)));
+    }
+
+    // A wall across x = 2 with a doorway at z = 0, `height` blocks tall
+    fn doorway(height: i32) -> VoxelWorld {
+        let mut world = world_with((-5..5).flat_map(|z| (1..5).map(move |y| IVec3::new(2, y, z))));
+        for y in 1..1 + height {
+            world.remove_block(IVec3::new(2, y, 0));
+        }
+        world
+    }
+
+    #[test]
+    fn only_shapes_that_fit_pass_through_a_doorway() {
+        let world = doorway(2);
+        let one_by_two = CollisionShape::default();
+        let one_by_three = CollisionShape { height: 2.8, eye_height: 2.6, ..one_by_two };
+
+        let start = Vec3::new(0.5, 1.0 + SKIN + one_by_two.eye_height, 0.5);
+        let (position, blocked) = super::move_and_slide(&world, one_by_two, start, Vec3::X * 3.0);
+        assert_near(position, start + Vec3::X * 3.0);
+        assert!(!blocked.x);
+
+        let start = Vec3::new(0.5, 1.0 + SKIN + one_by_three.eye_height, 0.5);
+        let (position, blocked) = super::move_and_slide(&world, one_by_three, start, Vec3::X * 3.0);
+        assert_near(position, Vec3::new(STOP, start.y, 0.5));
+        assert!(blocked.x);
+    }
+
+    #[test]
+    fn growing_back_under_a_low_ceiling_is_refused() {
+        let world = doorway(1);
+        let normal = CollisionShape::default();
+        let small = CollisionShape { height: 0.9, eye_height: 0.8, ..normal };
+
+        // Shrunk, e.g. by a powerup, then crawled into the doorway
+        let outside = Vec3::new(0.5, 1.0 + SKIN + normal.eye_height, 0.5);
+        let eye = resize(&world, outside, normal, small).unwrap();
+        let (inside, _) = super::move_and_slide(&world, small, eye, Vec3::X * 2.0);
+        assert_near(inside, eye + Vec3::X * 2.0);
+
+        // Standing up in there would put the head into the wall
+        assert_eq!(resize(&world, inside, small, normal), None);
+        assert!(!player_obstructed(&world, small, inside));
+
+        // Once out the other side there's room again
+        let (beyond, _) = super::move_and_slide(&world, small, inside, Vec3::X * 2.0);
+        let grown = resize(&world, beyond, small, normal).unwrap();
+        assert_near(grown, outside + Vec3::X * 4.0);
+        assert!(!player_obstructed(&world, normal, grown));
+    }
+
+    #[test]
+    fn crouching_keeps_the_feet_in_place() {
+        let world = VoxelWorld::default();
+        let standing = CollisionShape::default();
+        let eye = Vec3::new(0.5, 2.6, 0.5);
+
+        let crouched = resize(&world, eye, standing, standing.crouched()).unwrap();
+        assert_near(crouched, eye - Vec3::Y * CROUCH_DROP);
+        assert_near(resize(&world, crouched, standing.crouched(), standing).unwrap(), eye);
+    }
+}
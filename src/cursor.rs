@@ -0,0 +1,90 @@
+use bevy::{
+    input::mouse::MouseMotion,
+    prelude::*,
+    window::{CursorGrabMode, Window, WindowFocused},
+};
+
+
+#[derive(Debug, Resource)]
+pub struct CursorSettings {
+    pub release_key: KeyCode,
+    // Alt-tab doesn't always drop the grab, e.g. on some Wayland compositors
+    pub release_on_focus_loss: bool,
+}
+
+impl Default for CursorSettings {
+    fn default() -> Self {
+        Self {
+            release_key: KeyCode::Escape,
+            release_on_focus_loss: true,
+        }
+    }
+}
+
+// Single owner of the window's cursor lock; anything that needs the
+// mouse free (escape, chat) flips this instead of touching the window
+#[derive(Debug, Resource)]
+pub struct GrabState {
+    pub grabbed: bool,
+}
+
+impl Default for GrabState {
+    fn default() -> Self {
+        Self { grabbed: true }
+    }
+}
+
+// Run condition so mouse-look and clicks are ignored while the mouse is free
+pub fn is_grabbed(grab_state: Res<GrabState>) -> bool {
+    grab_state.grabbed
+}
+
+pub fn toggle_grab(
+    settings: Res<CursorSettings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut mouse_button: ResMut<ButtonInput<MouseButton>>,
+    mut focus_events: EventReader<WindowFocused>,
+    mut grab_state: ResMut<GrabState>,
+) {
+    let focus_lost = focus_events.read().any(|event| !event.focused);
+
+    if grab_state.grabbed {
+        if keyboard.just_pressed(settings.release_key) || (focus_lost && settings.release_on_focus_loss) {
+            grab_state.grabbed = false;
+        }
+    } else if mouse_button.just_pressed(MouseButton::Left) {
+        // The click only grabs the cursor, it shouldn't also place a block
+        mouse_button.clear_just_pressed(MouseButton::Left);
+        grab_state.grabbed = true;
+    }
+}
+
+pub fn apply_grab(
+    grab_state: Res<GrabState>,
+    mut windows: Query<&mut Window>,
+    mut mouse_motion: ResMut<Events<MouseMotion>>,
+) {
+    if !grab_state.is_changed() {
+        return;
+    }
+
+    let mut window = windows.single_mut();
+    window.cursor_options.grab_mode = if grab_state.grabbed {
+        CursorGrabMode::Locked
+    } else {
+        CursorGrabMode::None
+    };
+    window.cursor_options.visible = !grab_state.grabbed;
+
+    // Show the freed cursor in the middle, where the crosshair was
+    if !grab_state.grabbed {
+        let center = window.size() / 2.0;
+        window.set_cursor_position(Some(center));
+    }
+
+    // Motion from moving the free cursor around would otherwise all be
+    // applied to the camera on the first frame after grabbing again
+    if grab_state.grabbed {
+        mouse_motion.clear();
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt::Write;
+use bevy::prelude::*;
+
+use crate::{
+    budget::FrameBudget,
+    chat::ChatState,
+    history::HistoryUsage,
+    undo::EditHistory,
+};
+
+
+#[derive(Debug, Resource)]
+pub struct DebugOverlaySettings {
+    pub toggle_key: KeyCode,
+}
+
+impl Default for DebugOverlaySettings {
+    fn default() -> Self {
+        Self {
+            toggle_key: KeyCode::F3,
+        }
+    }
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct DebugOverlay {
+    pub visible: bool,
+}
+
+#[derive(Component)]
+pub struct DebugOverlayText;
+
+// Screenshot mode owns the root's visibility, so toggling shows and hides
+// the text node under it
+pub fn setup_debug_overlay(mut commands: Commands) {
+    commands
+        .spawn((
+            Name::new("Debug Overlay"),
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.0),
+                top: Val::Px(10.0),
+                ..default()
+            },
+        ))
+        .with_child((
+            DebugOverlayText,
+            Text::default(),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+            Visibility::Hidden,
+        ));
+}
+
+pub fn toggle_debug_overlay(
+    settings: Res<DebugOverlaySettings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut overlay: ResMut<DebugOverlay>,
+) {
+    if keyboard.just_pressed(settings.toggle_key) {
+        overlay.visible = !overlay.visible;
+    }
+}
+
+pub fn update_debug_overlay(
+    overlay: Res<DebugOverlay>,
+    budget: Res<FrameBudget>,
+    chat: Res<ChatState>,
+    edit_history: Res<EditHistory>,
+    mut text_query: Query<(&mut Text, &mut Visibility), With<DebugOverlayText>>,
+) {
+    let (mut text, mut visibility) = text_query.single_mut();
+    visibility.set_if_neq(if overlay.visible {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    });
+    if !overlay.visible {
+        return;
+    }
+
+    text.0 = budget_section(&budget);
+    text.0.push('\n');
+    text.0.push_str(&memory_section(&[
+        ("undo history", edit_history.usage()),
+        ("chat log", chat.messages.usage()),
+    ]));
+}
+
+// What each budgeted system spent of its share last frame, highest
+// priority first
+fn budget_section(budget: &FrameBudget) -> String {
+    let mut section = format!(
+        "Frame budget ({:.2} ms world work)\n",
+        budget.world_work.as_secs_f64() * 1000.0
+    );
+    for system in budget.systems() {
+        let _ = write!(
+            section,
+            "  {}: {:.2} / {:.2} ms, {} items",
+            system.name,
+            system.used.as_secs_f64() * 1000.0,
+            budget.share(system.name).as_secs_f64() * 1000.0,
+            system.items,
+        );
+        if system.overran {
+            section.push_str(", over budget");
+        }
+        section.push('\n');
+    }
+    section
+}
+
+// How full each bounded history is
+fn memory_section(histories: &[(&str, HistoryUsage)]) -> String {
+    let mut section = String::from("Memory\n");
+    for (name, usage) in histories {
+        let _ = write!(section, "  {name}: {} / {} entries", usage.len, usage.max_len);
+        if let Some((bytes, max_bytes)) = usage.bytes {
+            let _ = write!(section, ", {:.1} / {:.1} KiB", kib(bytes), kib(max_bytes));
+        }
+        section.push('\n');
+    }
+    section
+}
+
+fn kib(bytes: usize) -> f64 {
+    bytes as f64 / 1024.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_section_lists_every_system_by_priority() {
+        let mut budget = FrameBudget::default();
+        budget.register("lighting", 3);
+        budget.slice("terrain").item_done();
+
+        let section = budget_section(&budget);
+        let lines = section.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "Frame budget (3.00 ms world work)");
+        assert!(lines[1].starts_with("  lighting: 0.00 / 1.50 ms, 0 items"), "{}", lines[1]);
+        assert!(lines[2].starts_with("  meshing: 0.00 / 1.00 ms, 0 items"), "{}", lines[2]);
+        assert!(lines[3].starts_with("  terrain: "), "{}", lines[3]);
+        assert!(lines[3].contains("/ 0.50 ms, 1 items"), "{}", lines[3]);
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn memory_section_shows_entries_and_bytes() {
+        let histories = [
+            ("undo history", HistoryUsage { len: 12, max_len: 256, bytes: None }),
+            ("chat log", HistoryUsage { len: 3, max_len: 100, bytes: Some((512, 65536)) }),
+        ];
+        let section = memory_section(&histories);
+        let lines = section.lines().collect::<Vec<_>>();
+        assert_eq!(lines, [
+            "Memory",
+            "  undo history: 12 / 256 entries",
+            "  chat log: 3 / 100 entries, 0.5 / 64.0 KiB",
+        ]);
+    }
+}
@@ -0,0 +1,310 @@
+use bevy::{prelude::*, window::WindowCloseRequested};
+
+use crate::{
+    cursor::GrabState,
+    locations::NamedLocations,
+    save::{self, SaveSettings, UnsavedChanges},
+    voxel::VoxelWorld,
+};
+
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExitState {
+    #[default]
+    Closed,
+    Asking,
+    // Shown for a frame before the save blocks, so the player can see why
+    // the game stopped responding
+    Saving,
+    SaveFailed,
+}
+
+// Asks before closing the window would throw away unsaved edits
+#[derive(Debug, Resource)]
+pub struct ExitPrompt {
+    pub state: ExitState,
+    // Cancelling puts the cursor back the way it was when the prompt opened
+    pub was_grabbed: bool,
+}
+
+impl Default for ExitPrompt {
+    fn default() -> Self {
+        Self {
+            state: ExitState::Closed,
+            was_grabbed: true,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct ExitPromptRoot;
+
+#[derive(Component)]
+pub struct ExitPromptText;
+
+// Window closing is turned off in `WindowPlugin`, so nothing closes
+// unless this or the prompt sends `AppExit`
+pub fn request_exit(
+    mut close_requests: EventReader<WindowCloseRequested>,
+    world: Res<VoxelWorld>,
+    locations: Res<NamedLocations>,
+    unsaved: Res<UnsavedChanges>,
+    mut prompt: ResMut<ExitPrompt>,
+    mut grab_state: ResMut<GrabState>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if close_requests.read().count() == 0 || prompt.state != ExitState::Closed {
+        return;
+    }
+
+    if !unsaved.is_dirty(&world, &locations) {
+        exit.send(AppExit::Success);
+        return;
+    }
+
+    prompt.state = ExitState::Asking;
+    prompt.was_grabbed = grab_state.grabbed;
+    grab_state.grabbed = false;
+}
+
+pub fn exit_prompt_input(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut prompt: ResMut<ExitPrompt>,
+    mut grab_state: ResMut<GrabState>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if !matches!(prompt.state, ExitState::Asking | ExitState::SaveFailed) {
+        return;
+    }
+
+    if keyboard.just_pressed(KeyCode::Enter) {
+        prompt.state = ExitState::Saving;
+    } else if keyboard.just_pressed(KeyCode::KeyQ) {
+        exit.send(AppExit::Success);
+    } else if keyboard.just_pressed(KeyCode::Escape) {
+        prompt.state = ExitState::Closed;
+        grab_state.grabbed = prompt.was_grabbed;
+    }
+}
+
+// Runs before the input that starts it, so the saving notice has been
+// drawn once by the time this blocks
+pub fn finish_exit_save(
+    camera_query: Query<&Transform, With<Camera>>,
+    settings: Res<SaveSettings>,
+    world: Res<VoxelWorld>,
+    locations: Res<NamedLocations>,
+    mut unsaved: ResMut<UnsavedChanges>,
+    mut prompt: ResMut<ExitPrompt>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if prompt.state != ExitState::Saving {
+        return;
+    }
+
+    if save::save_to_file(&settings.path, &world, &locations, camera_query.single()) {
+        unsaved.mark_saved(&world, &locations);
+        exit.send(AppExit::Success);
+    } else {
+        // Never quit after a failed save; the player can retry or give up
+        prompt.state = ExitState::SaveFailed;
+    }
+}
+
+pub fn setup_exit_prompt(mut commands: Commands) {
+    commands
+        .spawn((
+            Name::new("Exit Prompt"),
+            ExitPromptRoot,
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            Visibility::Hidden,
+        ))
+        .with_child((
+            ExitPromptText,
+            Text::default(),
+            TextFont {
+                font_size: 20.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+            TextLayout::new_with_justify(JustifyText::Center),
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.8)),
+            Node {
+                padding: UiRect::all(Val::Px(16.0)),
+                ..default()
+            },
+        ));
+}
+
+pub fn update_exit_prompt_ui(
+    prompt: Res<ExitPrompt>,
+    settings: Res<SaveSettings>,
+    world: Res<VoxelWorld>,
+    mut root_query: Query<&mut Visibility, With<ExitPromptRoot>>,
+    mut text_query: Query<&mut Text, With<ExitPromptText>>,
+) {
+    if !prompt.is_changed() {
+        return;
+    }
+
+    *root_query.single_mut() = if prompt.state == ExitState::Closed {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    };
+
+    let choices = "[Enter] Save and quit   [Q] Quit without saving   [Esc] Cancel";
+    text_query.single_mut().0 = match prompt.state {
+        ExitState::Closed => String::new(),
+        ExitState::Asking => format!("There are unsaved changes.\n\n{choices}"),
+        ExitState::Saving => format!(
+            "Saving {} blocks to {}...",
+            world.block_count(),
+            settings.path.display()
+        ),
+        ExitState::SaveFailed => format!("Saving failed, see the log.\n\n{choices}"),
+    };
+}
+
+pub fn is_closed(prompt: Res<ExitPrompt>) -> bool {
+    prompt.state == ExitState::Closed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::voxel::BlockType;
+
+    fn exit_world(path: PathBuf) -> World {
+        let mut world = World::new();
+        world.insert_resource(SaveSettings { path, ..default() });
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<VoxelWorld>();
+        world.init_resource::<NamedLocations>();
+        world.init_resource::<UnsavedChanges>();
+        world.init_resource::<ExitPrompt>();
+        world.init_resource::<GrabState>();
+        world.init_resource::<Events<WindowCloseRequested>>();
+        world.init_resource::<Events<AppExit>>();
+        world.spawn((Camera3d::default(), Transform::default()));
+        world
+    }
+
+    // One frame, in the order the app runs these
+    fn frame(world: &mut World, keys: &[KeyCode]) {
+        let mut keyboard = world.resource_mut::<ButtonInput<KeyCode>>();
+        keyboard.reset_all();
+        for &key in keys {
+            keyboard.press(key);
+        }
+        world.run_system_once(request_exit).unwrap();
+        world.run_system_once(finish_exit_save).unwrap();
+        world.run_system_once(exit_prompt_input).unwrap();
+        world.resource_mut::<Events<WindowCloseRequested>>().clear();
+    }
+
+    fn close_window(world: &mut World) {
+        world.send_event(WindowCloseRequested { window: Entity::PLACEHOLDER });
+        frame(world, &[]);
+    }
+
+    fn exited(world: &mut World) -> bool {
+        world.resource_mut::<Events<AppExit>>().drain().count() > 0
+    }
+
+    fn state(world: &World) -> ExitState {
+        world.resource::<ExitPrompt>().state
+    }
+
+    fn edit(world: &mut World) {
+        world.resource_mut::<VoxelWorld>().set_block(IVec3::ZERO, BlockType::Stone);
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("castle_wars-{}-exit-{name}.ron", std::process::id()))
+    }
+
+    #[test]
+    fn closes_at_once_without_unsaved_changes() {
+        let mut world = exit_world(temp_path("clean"));
+        close_window(&mut world);
+        assert!(exited(&mut world));
+        assert_eq!(state(&world), ExitState::Closed);
+    }
+
+    #[test]
+    fn asks_before_losing_changes_and_can_cancel() {
+        let mut world = exit_world(temp_path("cancel"));
+        edit(&mut world);
+
+        close_window(&mut world);
+        assert!(!exited(&mut world));
+        assert_eq!(state(&world), ExitState::Asking);
+        assert!(!world.resource::<GrabState>().grabbed);
+
+        frame(&mut world, &[KeyCode::Escape]);
+        assert!(!exited(&mut world));
+        assert_eq!(state(&world), ExitState::Closed);
+        assert!(world.resource::<GrabState>().grabbed);
+    }
+
+    #[test]
+    fn quits_without_saving() {
+        let path = temp_path("discard");
+        let mut world = exit_world(path.clone());
+        edit(&mut world);
+        close_window(&mut world);
+
+        frame(&mut world, &[KeyCode::KeyQ]);
+        assert!(exited(&mut world));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn saves_on_the_frame_after_asking_then_quits() {
+        let path = temp_path("save");
+        let mut world = exit_world(path.clone());
+        edit(&mut world);
+        close_window(&mut world);
+
+        frame(&mut world, &[KeyCode::Enter]);
+        assert_eq!(state(&world), ExitState::Saving);
+        assert!(!exited(&mut world));
+        assert!(!path.exists());
+
+        frame(&mut world, &[]);
+        assert!(exited(&mut world));
+        assert!(fs::read_to_string(&path).unwrap().contains("blocks"));
+        fs::remove_file(&path).unwrap();
+        let unsaved = world.resource::<UnsavedChanges>();
+        let voxels = world.resource::<VoxelWorld>();
+        assert!(!unsaved.is_dirty(voxels, world.resource::<NamedLocations>()));
+    }
+
+    #[test]
+    fn stays_open_when_the_save_fails() {
+        let mut world = exit_world(temp_path("missing-dir").join("world.ron"));
+        edit(&mut world);
+        close_window(&mut world);
+
+        frame(&mut world, &[KeyCode::Enter]);
+        frame(&mut world, &[]);
+        assert!(!exited(&mut world));
+        assert_eq!(state(&world), ExitState::SaveFailed);
+
+        // Giving up is still possible
+        frame(&mut world, &[KeyCode::KeyQ]);
+        assert!(exited(&mut world));
+    }
+}
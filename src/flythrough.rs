@@ -0,0 +1,231 @@
+use std::{fs, path::PathBuf};
+use bevy::prelude::*;
+
+
+#[derive(Debug, Resource)]
+pub struct FlythroughSettings {
+    pub record_key: KeyCode,
+    pub play_key: KeyCode,
+    pub clear_key: KeyCode,
+    pub save_key: KeyCode,
+    pub load_key: KeyCode,
+    pub duration: f32,
+    pub path: PathBuf,
+}
+
+impl Default for FlythroughSettings {
+    fn default() -> Self {
+        Self {
+            record_key: KeyCode::KeyP,
+            play_key: KeyCode::KeyO,
+            clear_key: KeyCode::KeyI,
+            save_key: KeyCode::F6,
+            load_key: KeyCode::F8,
+            duration: 10.0,
+            path: PathBuf::from("flythrough.txt"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct Flythrough {
+    pub waypoints: Vec<Transform>,
+    // Seconds into the current playback, `None` while not playing
+    pub elapsed: Option<f32>,
+}
+
+// Run condition used to suspend camera input while a flythrough plays
+pub fn is_idle(flythrough: Res<Flythrough>) -> bool {
+    flythrough.elapsed.is_none()
+}
+
+pub fn edit_flythrough(
+    camera_query: Query<&Transform, With<Camera>>,
+    settings: Res<FlythroughSettings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut flythrough: ResMut<Flythrough>,
+) {
+    if keyboard.just_pressed(settings.play_key) {
+        if flythrough.elapsed.is_some() {
+            flythrough.elapsed = None;
+        } else if flythrough.waypoints.len() >= 2 {
+            flythrough.elapsed = Some(0.0);
+        } else {
+            warn!("Flythrough needs at least 2 waypoints to play");
+        }
+    }
+
+    // Editing the path mid-playback would pull the camera around
+    if flythrough.elapsed.is_some() {
+        return;
+    }
+
+    if keyboard.just_pressed(settings.record_key) {
+        let camera = camera_query.single();
+        flythrough.waypoints.push(*camera);
+        info!("Added flythrough waypoint {}", flythrough.waypoints.len());
+    }
+    if keyboard.just_pressed(settings.clear_key) {
+        flythrough.waypoints.clear();
+        info!("Cleared flythrough waypoints");
+    }
+    if keyboard.just_pressed(settings.save_key) {
+        match fs::write(&settings.path, serialize_waypoints(&flythrough.waypoints)) {
+            Ok(()) => info!("Saved flythrough to {}", settings.path.display()),
+            Err(err) => error!("Failed to save flythrough to {}: {err}", settings.path.display()),
+        }
+    }
+    if keyboard.just_pressed(settings.load_key) {
+        match fs::read_to_string(&settings.path) {
+            Ok(contents) => match parse_waypoints(&contents) {
+                Some(waypoints) => {
+                    info!("Loaded {} flythrough waypoints", waypoints.len());
+                    flythrough.waypoints = waypoints;
+                }
+                None => error!("Malformed flythrough file {}", settings.path.display()),
+            },
+            Err(err) => warn!("Failed to load flythrough from {}: {err}", settings.path.display()),
+        }
+    }
+}
+
+pub fn play_flythrough(
+    mut camera_query: Query<&mut Transform, With<Camera>>,
+    settings: Res<FlythroughSettings>,
+    mut flythrough: ResMut<Flythrough>,
+    time: Res<Time>,
+) {
+    let Some(elapsed) = flythrough.elapsed else {
+        return;
+    };
+
+    let elapsed = elapsed + time.delta_secs();
+    let t = elapsed / settings.duration.max(f32::EPSILON);
+
+    let mut camera = camera_query.single_mut();
+    *camera = sample_path(&flythrough.waypoints, t);
+
+    flythrough.elapsed = if t < 1.0 { Some(elapsed) } else { None };
+}
+
+// Catmull-Rom through the waypoint translations, slerp between rotations.
+// `t` runs from 0.0 at the first waypoint to 1.0 at the last.
+fn sample_path(waypoints: &[Transform], t: f32) -> Transform {
+    let last = waypoints.len() - 1;
+    let scaled = t.clamp(0.0, 1.0) * last as f32;
+    let segment = (scaled.floor() as usize).min(last - 1);
+    let u = scaled - segment as f32;
+
+    let point = |i: isize| waypoints[i.clamp(0, last as isize) as usize].translation;
+    let i = segment as isize;
+    let translation = catmull_rom(point(i - 1), point(i), point(i + 1), point(i + 2), u);
+    let rotation = waypoints[segment]
+        .rotation
+        .slerp(waypoints[segment + 1].rotation, u);
+
+    Transform::from_translation(translation).with_rotation(rotation)
+}
+
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+// One waypoint per line: translation xyz followed by rotation xyzw
+fn serialize_waypoints(waypoints: &[Transform]) -> String {
+    waypoints
+        .iter()
+        .map(|w| {
+            let (t, r) = (w.translation, w.rotation);
+            format!("{} {} {} {} {} {} {}\n", t.x, t.y, t.z, r.x, r.y, r.z, r.w)
+        })
+        .collect()
+}
+
+fn parse_waypoints(contents: &str) -> Option<Vec<Transform>> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let values = line
+                .split_whitespace()
+                .map(|v| v.parse::<f32>().ok())
+                .collect::<Option<Vec<_>>>()?;
+            let [tx, ty, tz, rx, ry, rz, rw] = values[..] else {
+                return None;
+            };
+            Some(
+                Transform::from_xyz(tx, ty, tz)
+                    .with_rotation(Quat::from_xyzw(rx, ry, rz, rw).normalize()),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn waypoints() -> Vec<Transform> {
+        vec![
+            Transform::from_xyz(0.0, 5.0, 0.0),
+            Transform::from_xyz(10.0, 6.0, -4.0).with_rotation(Quat::from_rotation_y(1.0)),
+            Transform::from_xyz(20.0, 8.0, 3.5).with_rotation(Quat::from_rotation_x(-0.5)),
+            Transform::from_xyz(-7.25, 12.0, 9.0),
+        ]
+    }
+
+    fn assert_close(a: Transform, b: Transform) {
+        assert!(a.translation.distance(b.translation) < 1e-4, "{a:?} != {b:?}");
+        assert!(a.rotation.angle_between(b.rotation) < 1e-3, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn waypoints_survive_a_save_and_load() {
+        let waypoints = waypoints();
+        let loaded = parse_waypoints(&serialize_waypoints(&waypoints)).unwrap();
+
+        assert_eq!(loaded.len(), waypoints.len());
+        for (loaded, waypoint) in loaded.into_iter().zip(waypoints) {
+            assert_close(loaded, waypoint);
+        }
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        assert!(parse_waypoints("1 2 3 0 0 0 1\n1 2 3\n").is_none());
+        assert!(parse_waypoints("1 2 3 0 0 0 one\n").is_none());
+        assert_eq!(parse_waypoints("\n1 2 3 0 0 0 1\n\n").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn path_starts_and_ends_on_its_waypoints() {
+        let waypoints = waypoints();
+        assert_close(sample_path(&waypoints, 0.0), waypoints[0]);
+        assert_close(sample_path(&waypoints, 1.0), waypoints[3]);
+        // Outside the playback clamps rather than overshooting
+        assert_close(sample_path(&waypoints, -0.5), waypoints[0]);
+        assert_close(sample_path(&waypoints, 1.5), waypoints[3]);
+    }
+
+    #[test]
+    fn path_passes_through_every_waypoint() {
+        let waypoints = waypoints();
+        let last = (waypoints.len() - 1) as f32;
+        for (i, waypoint) in waypoints.iter().enumerate() {
+            assert_close(sample_path(&waypoints, i as f32 / last), *waypoint);
+        }
+    }
+
+    #[test]
+    fn two_waypoints_give_a_straight_line() {
+        let waypoints = &waypoints()[..2];
+        let middle = sample_path(waypoints, 0.5).translation;
+        assert!(middle.distance(Vec3::new(5.0, 5.5, -2.0)) < 1e-4, "{middle}");
+    }
+}
@@ -0,0 +1,559 @@
+use std::collections::VecDeque;
+use bevy::{
+    core_pipeline::{
+        contrast_adaptive_sharpening::ContrastAdaptiveSharpening,
+        experimental::taa::{TemporalAntiAliasPlugin, TemporalAntiAliasing},
+        fxaa::Fxaa,
+        prepass::{DepthPrepass, MotionVectorPrepass},
+    },
+    pbr::{DistanceFog, FogFalloff},
+    prelude::*,
+    render::camera::TemporalJitter,
+    window::PresentMode,
+};
+
+use crate::chat::ChatState;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Antialiasing {
+    Off,
+    Msaa2,
+    Msaa4,
+    Fxaa,
+    Taa,
+}
+
+impl Antialiasing {
+    // Cycle order
+    pub const ALL: [Antialiasing; 5] = [
+        Antialiasing::Off,
+        Antialiasing::Msaa2,
+        Antialiasing::Msaa4,
+        Antialiasing::Fxaa,
+        Antialiasing::Taa,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Antialiasing::Off => "off",
+            Antialiasing::Msaa2 => "msaa2",
+            Antialiasing::Msaa4 => "msaa4",
+            Antialiasing::Fxaa => "fxaa",
+            Antialiasing::Taa => "taa",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Antialiasing> {
+        Antialiasing::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+
+    // What actually runs: TAA falls back to MSAA 4x where it can't
+    fn supported(self, support: &AntialiasingSupport) -> Antialiasing {
+        match self {
+            Antialiasing::Taa if !support.taa => Antialiasing::Msaa4,
+            mode => mode,
+        }
+    }
+
+    // The post-process modes need MSAA off to run at all
+    fn msaa(self) -> Msaa {
+        match self {
+            Antialiasing::Msaa2 => Msaa::Sample2,
+            Antialiasing::Msaa4 => Msaa::Sample4,
+            Antialiasing::Off | Antialiasing::Fxaa | Antialiasing::Taa => Msaa::Off,
+        }
+    }
+}
+
+// Bundles of the settings below, lowest first. `apply` is the only place
+// a preset is turned into settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityPreset {
+    Low,
+    Medium,
+    High,
+}
+
+impl QualityPreset {
+    pub const ALL: [QualityPreset; 3] = [
+        QualityPreset::Low,
+        QualityPreset::Medium,
+        QualityPreset::High,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            QualityPreset::Low => "low",
+            QualityPreset::Medium => "medium",
+            QualityPreset::High => "high",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<QualityPreset> {
+        QualityPreset::ALL.into_iter().find(|preset| preset.name() == name)
+    }
+
+    pub fn lower(self) -> Option<QualityPreset> {
+        match self {
+            QualityPreset::Low => None,
+            QualityPreset::Medium => Some(QualityPreset::Low),
+            QualityPreset::High => Some(QualityPreset::Medium),
+        }
+    }
+
+    pub fn apply(self, settings: &mut GraphicsSettings) {
+        settings.quality = Some(self);
+        // Fog hides where the shorter view distances cut off
+        let (antialiasing, shadows, fog, render_distance) = match self {
+            QualityPreset::Low => (Antialiasing::Off, false, true, 96.0),
+            QualityPreset::Medium => (Antialiasing::Fxaa, false, true, 192.0),
+            QualityPreset::High => (Antialiasing::Msaa4, true, false, 1000.0),
+        };
+        settings.antialiasing = antialiasing;
+        settings.sharpening = false;
+        settings.shadows = shadows;
+        settings.fog = fog;
+        settings.render_distance = render_distance;
+        // Without vsync a machine that can't keep up with the display
+        // doesn't drop straight to half its refresh rate
+        settings.vsync = self != QualityPreset::Low;
+    }
+}
+
+// TAA needs its plugin and the depth and motion vector prepasses, which
+// WebGL2 doesn't have
+#[derive(Debug, Resource)]
+pub struct AntialiasingSupport {
+    pub taa: bool,
+}
+
+// Adds TAA only where it can run and records whether it did
+pub fn antialiasing_plugin(app: &mut App) {
+    let taa = !cfg!(target_arch = "wasm32");
+    if taa {
+        app.add_plugins(TemporalAntiAliasPlugin);
+    }
+    app.insert_resource(AntialiasingSupport { taa });
+}
+
+#[derive(Debug, Resource)]
+pub struct GraphicsSettings {
+    // The preset the settings below came from, or None once any of them
+    // has been changed by hand
+    pub quality: Option<QualityPreset>,
+    pub antialiasing: Antialiasing,
+    pub antialiasing_key: KeyCode,
+    // Contrast-adaptive sharpening, mostly to win back detail lost to TAA
+    pub sharpening: bool,
+    pub sharpening_strength: f32,
+    pub sharpening_key: KeyCode,
+    pub shadows: bool,
+    pub fog: bool,
+    // Far plane of the camera
+    pub render_distance: f32,
+    pub vsync: bool,
+    // Steps the preset down while frames are slow, see `auto_quality`
+    pub auto_quality: bool,
+    pub auto_quality_threshold_ms: f32,
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        let mut settings = Self {
+            quality: None,
+            antialiasing: Antialiasing::Msaa4,
+            antialiasing_key: KeyCode::F7,
+            sharpening: false,
+            sharpening_strength: 0.6,
+            sharpening_key: KeyCode::F4,
+            shadows: true,
+            fog: false,
+            render_distance: 1000.0,
+            vsync: true,
+            auto_quality: false,
+            auto_quality_threshold_ms: 40.0,
+        };
+        QualityPreset::High.apply(&mut settings);
+        settings
+    }
+}
+
+pub fn graphics_input(keyboard: Res<ButtonInput<KeyCode>>, mut settings: ResMut<GraphicsSettings>) {
+    if keyboard.just_pressed(settings.antialiasing_key) {
+        let index = Antialiasing::ALL
+            .iter()
+            .position(|mode| *mode == settings.antialiasing)
+            .unwrap_or(0);
+        settings.antialiasing = Antialiasing::ALL[(index + 1) % Antialiasing::ALL.len()];
+        settings.quality = None;
+        info!("Anti-aliasing: {}", settings.antialiasing.name());
+    }
+    if keyboard.just_pressed(settings.sharpening_key) {
+        settings.sharpening = !settings.sharpening;
+        settings.quality = None;
+        info!("Sharpening: {}", if settings.sharpening { "on" } else { "off" });
+    }
+}
+
+// Swaps the camera's anti-aliasing components whenever the settings change.
+// The prepasses TAA pulled in are removed with it so other modes don't pay
+// for them.
+pub fn apply_graphics_settings(
+    mut commands: Commands,
+    settings: Res<GraphicsSettings>,
+    support: Res<AntialiasingSupport>,
+    clear_color: Res<ClearColor>,
+    mut camera_query: Query<(Entity, Option<&mut Projection>), With<Camera3d>>,
+    mut light_query: Query<&mut PointLight>,
+    mut windows: Query<&mut Window>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    let antialiasing = settings.antialiasing.supported(&support);
+    if antialiasing != settings.antialiasing {
+        warn!(
+            "Anti-aliasing {} isn't available here, using {}",
+            settings.antialiasing.name(),
+            antialiasing.name()
+        );
+    }
+
+    for mut light in &mut light_query {
+        light.shadows_enabled = settings.shadows;
+    }
+    for mut window in &mut windows {
+        window.present_mode = if settings.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        };
+    }
+
+    for (camera, projection) in &mut camera_query {
+        if let Some(Projection::Perspective(perspective)) = projection.map(Mut::into_inner) {
+            perspective.far = settings.render_distance;
+        }
+
+        let mut camera = commands.entity(camera);
+        if settings.fog {
+            camera.insert(DistanceFog {
+                color: clear_color.0,
+                falloff: FogFalloff::Linear {
+                    start: settings.render_distance * 0.6,
+                    end: settings.render_distance,
+                },
+                ..default()
+            });
+        } else {
+            camera.remove::<DistanceFog>();
+        }
+
+        camera
+            .insert(antialiasing.msaa())
+            .remove::<(
+                Fxaa,
+                TemporalAntiAliasing,
+                TemporalJitter,
+                DepthPrepass,
+                MotionVectorPrepass,
+            )>()
+            .insert(ContrastAdaptiveSharpening {
+                enabled: settings.sharpening,
+                sharpening_strength: settings.sharpening_strength,
+                ..default()
+            });
+
+        match antialiasing {
+            Antialiasing::Fxaa => {
+                camera.insert(Fxaa::default());
+            }
+            Antialiasing::Taa => {
+                camera.insert(TemporalAntiAliasing::default());
+            }
+            Antialiasing::Off | Antialiasing::Msaa2 | Antialiasing::Msaa4 => {}
+        }
+    }
+}
+
+// Frames the p95 is taken over
+const AUTO_QUALITY_FRAMES: usize = 120;
+// How long frames have to stay slow before the preset steps down, and how
+// long after one step before the next
+const AUTO_QUALITY_SLOW_SECS: f32 = 10.0;
+const AUTO_QUALITY_STEP_SECS: f32 = 60.0;
+
+#[derive(Debug, Default, Resource)]
+pub struct AutoQuality {
+    frame_ms: VecDeque<f32>,
+    slow_since: Option<f32>,
+    last_step: Option<f32>,
+}
+
+impl AutoQuality {
+    // None until there are enough frames for it to mean anything
+    fn p95_frame_ms(&self) -> Option<f32> {
+        if self.frame_ms.len() < AUTO_QUALITY_FRAMES {
+            return None;
+        }
+        let mut sorted = self.frame_ms.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable_by(f32::total_cmp);
+        Some(sorted[AUTO_QUALITY_FRAMES * 95 / 100])
+    }
+
+    // Records a frame and says whether it's time to step down
+    fn observe(&mut self, frame_ms: f32, now: f32, threshold_ms: f32) -> bool {
+        if self.frame_ms.len() == AUTO_QUALITY_FRAMES {
+            self.frame_ms.pop_front();
+        }
+        self.frame_ms.push_back(frame_ms);
+
+        match self.p95_frame_ms() {
+            Some(p95) if p95 > threshold_ms => {
+                let slow_since = *self.slow_since.get_or_insert(now);
+                now - slow_since >= AUTO_QUALITY_SLOW_SECS
+                    && self.last_step.is_none_or(|last| now - last >= AUTO_QUALITY_STEP_SECS)
+            }
+            _ => {
+                self.slow_since = None;
+                false
+            }
+        }
+    }
+
+    // Frames from before the step say nothing about the new preset
+    fn restart(&mut self) {
+        self.frame_ms.clear();
+        self.slow_since = None;
+    }
+}
+
+// Only while a preset is in use, so settings changed by hand are left alone
+pub fn auto_quality(
+    mut settings: ResMut<GraphicsSettings>,
+    mut auto: ResMut<AutoQuality>,
+    mut chat: ResMut<ChatState>,
+    windows: Query<&Window>,
+    time: Res<Time>,
+) {
+    let Some(preset) = settings.quality.filter(|_| settings.auto_quality) else {
+        return;
+    };
+
+    // The app is throttled in the background, which isn't slowness to act on
+    if windows.iter().any(|window| !window.focused) {
+        auto.restart();
+        return;
+    }
+
+    let now = time.elapsed_secs();
+    let threshold_ms = settings.auto_quality_threshold_ms;
+    if !auto.observe(time.delta_secs() * 1000.0, now, threshold_ms) {
+        return;
+    }
+    let Some(lower) = preset.lower() else {
+        return;
+    };
+
+    lower.apply(&mut settings);
+    auto.last_step = Some(now);
+    auto.restart();
+    chat.push_system(format!("Frames are slow, lowered graphics quality to {}", lower.name()), now);
+    info!("Auto quality: lowered to {}", lower.name());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    fn camera_with(antialiasing: Antialiasing, taa: bool) -> (World, Entity) {
+        camera_with_settings(GraphicsSettings { antialiasing, ..default() }, taa)
+    }
+
+    fn camera_with_settings(settings: GraphicsSettings, taa: bool) -> (World, Entity) {
+        let mut world = World::new();
+        world.insert_resource(settings);
+        world.insert_resource(AntialiasingSupport { taa });
+        world.init_resource::<ClearColor>();
+        let camera = world.spawn(Camera3d::default()).id();
+        world.run_system_once(apply_graphics_settings).unwrap();
+        (world, camera)
+    }
+
+    fn has<C: Component>(world: &World, camera: Entity) -> bool {
+        world.get::<C>(camera).is_some()
+    }
+
+    #[test]
+    fn each_mode_sets_up_the_camera_it_needs() {
+        for mode in Antialiasing::ALL {
+            let (world, camera) = camera_with(mode, true);
+            let msaa = *world.get::<Msaa>(camera).unwrap();
+
+            match mode {
+                Antialiasing::Off => assert_eq!(msaa, Msaa::Off),
+                Antialiasing::Msaa2 => assert_eq!(msaa, Msaa::Sample2),
+                Antialiasing::Msaa4 => assert_eq!(msaa, Msaa::Sample4),
+                Antialiasing::Fxaa | Antialiasing::Taa => assert_eq!(msaa, Msaa::Off),
+            }
+            assert_eq!(has::<Fxaa>(&world, camera), mode == Antialiasing::Fxaa, "{mode:?}");
+            let taa = mode == Antialiasing::Taa;
+            assert_eq!(has::<TemporalAntiAliasing>(&world, camera), taa, "{mode:?}");
+            assert_eq!(has::<DepthPrepass>(&world, camera), taa, "{mode:?}");
+            assert_eq!(has::<MotionVectorPrepass>(&world, camera), taa, "{mode:?}");
+            assert!(has::<ContrastAdaptiveSharpening>(&world, camera));
+        }
+    }
+
+    #[test]
+    fn switching_away_from_taa_removes_its_prepasses() {
+        let (mut world, camera) = camera_with(Antialiasing::Taa, true);
+        world.resource_mut::<GraphicsSettings>().antialiasing = Antialiasing::Fxaa;
+        world.run_system_once(apply_graphics_settings).unwrap();
+
+        assert!(has::<Fxaa>(&world, camera));
+        assert!(!has::<TemporalAntiAliasing>(&world, camera));
+        assert!(!has::<TemporalJitter>(&world, camera));
+        assert!(!has::<DepthPrepass>(&world, camera));
+        assert!(!has::<MotionVectorPrepass>(&world, camera));
+    }
+
+    #[test]
+    fn taa_falls_back_to_msaa_where_unsupported() {
+        let (world, camera) = camera_with(Antialiasing::Taa, false);
+        assert_eq!(*world.get::<Msaa>(camera).unwrap(), Msaa::Sample4);
+        assert!(!has::<TemporalAntiAliasing>(&world, camera));
+        assert!(!has::<DepthPrepass>(&world, camera));
+    }
+
+    // Everything a preset sets
+    fn bundle(settings: &GraphicsSettings) -> (Antialiasing, bool, bool, bool, f32, bool) {
+        (
+            settings.antialiasing,
+            settings.sharpening,
+            settings.shadows,
+            settings.fog,
+            settings.render_distance,
+            settings.vsync,
+        )
+    }
+
+    #[test]
+    fn defaults_are_the_high_preset() {
+        let defaults = GraphicsSettings::default();
+        assert_eq!(defaults.quality, Some(QualityPreset::High));
+
+        let mut changed = GraphicsSettings {
+            antialiasing: Antialiasing::Taa,
+            sharpening: true,
+            shadows: false,
+            render_distance: 10.0,
+            quality: None,
+            ..default()
+        };
+        QualityPreset::High.apply(&mut changed);
+        assert_eq!(bundle(&changed), bundle(&defaults));
+        assert_eq!(changed.quality, Some(QualityPreset::High));
+    }
+
+    #[test]
+    fn lower_presets_cost_less() {
+        let mut low = GraphicsSettings::default();
+        QualityPreset::Low.apply(&mut low);
+        assert_eq!(QualityPreset::High.lower(), Some(QualityPreset::Medium));
+        assert_eq!(QualityPreset::Medium.lower(), Some(QualityPreset::Low));
+        assert_eq!(QualityPreset::Low.lower(), None);
+        assert_eq!(low.antialiasing, Antialiasing::Off);
+        assert!(!low.shadows && low.fog && !low.vsync);
+        assert!(low.render_distance < GraphicsSettings::default().render_distance);
+    }
+
+    #[test]
+    fn presets_set_up_the_camera_and_light() {
+        let mut settings = GraphicsSettings::default();
+        QualityPreset::Low.apply(&mut settings);
+        let (mut world, camera) = camera_with_settings(settings, true);
+        let light = world.spawn(PointLight { shadows_enabled: true, ..default() }).id();
+        world.resource_mut::<GraphicsSettings>().set_changed();
+        world.run_system_once(apply_graphics_settings).unwrap();
+
+        assert!(has::<DistanceFog>(&world, camera));
+        assert!(!world.get::<PointLight>(light).unwrap().shadows_enabled);
+        let Projection::Perspective(perspective) = world.get::<Projection>(camera).unwrap() else {
+            panic!("camera isn't perspective");
+        };
+        assert_eq!(perspective.far, 96.0);
+
+        QualityPreset::High.apply(&mut world.resource_mut::<GraphicsSettings>());
+        world.run_system_once(apply_graphics_settings).unwrap();
+        assert!(!has::<DistanceFog>(&world, camera));
+        assert!(world.get::<PointLight>(light).unwrap().shadows_enabled);
+    }
+
+    fn auto_world(quality: Option<QualityPreset>) -> World {
+        let mut world = World::new();
+        world.insert_resource(GraphicsSettings { quality, auto_quality: true, ..default() });
+        world.init_resource::<AutoQuality>();
+        world.init_resource::<ChatState>();
+        world.init_resource::<Time>();
+        world
+    }
+
+    fn run_frames(world: &mut World, seconds: f32, frame_ms: f32) {
+        for _ in 0..(seconds * 1000.0 / frame_ms) as usize {
+            let delta = Duration::from_secs_f32(frame_ms / 1000.0);
+            world.resource_mut::<Time>().advance_by(delta);
+            world.run_system_once(auto_quality).unwrap();
+        }
+    }
+
+    fn quality(world: &World) -> Option<QualityPreset> {
+        world.resource::<GraphicsSettings>().quality
+    }
+
+    #[test]
+    fn slow_frames_step_the_preset_down_at_most_once_a_minute() {
+        let mut world = auto_world(Some(QualityPreset::High));
+
+        // Six seconds to fill the window, then ten of being slow
+        run_frames(&mut world, 15.0, 50.0);
+        assert_eq!(quality(&world), Some(QualityPreset::High));
+        run_frames(&mut world, 2.0, 50.0);
+        assert_eq!(quality(&world), Some(QualityPreset::Medium));
+        assert_eq!(world.resource::<GraphicsSettings>().antialiasing, Antialiasing::Fxaa);
+        assert_eq!(world.resource::<ChatState>().messages.len(), 1);
+
+        run_frames(&mut world, 55.0, 50.0);
+        assert_eq!(quality(&world), Some(QualityPreset::Medium));
+        run_frames(&mut world, 10.0, 50.0);
+        assert_eq!(quality(&world), Some(QualityPreset::Low));
+
+        // Nothing lower to go to
+        run_frames(&mut world, 120.0, 50.0);
+        assert_eq!(quality(&world), Some(QualityPreset::Low));
+    }
+
+    #[test]
+    fn short_bursts_of_slow_frames_are_ignored() {
+        let mut world = auto_world(Some(QualityPreset::High));
+        for _ in 0..4 {
+            run_frames(&mut world, 8.0, 50.0);
+            run_frames(&mut world, 2.0, 10.0);
+        }
+        assert_eq!(quality(&world), Some(QualityPreset::High));
+    }
+
+    #[test]
+    fn hand_picked_settings_are_left_alone() {
+        let mut world = auto_world(None);
+        run_frames(&mut world, 30.0, 50.0);
+        assert_eq!(quality(&world), None);
+        assert_eq!(world.resource::<GraphicsSettings>().antialiasing, Antialiasing::Msaa4);
+    }
+}
@@ -0,0 +1,263 @@
+use std::{collections::VecDeque, fmt};
+
+
+type EvictionCallback<T> = Box<dyn FnMut(T) + Send + Sync>;
+
+// Append-only log capped by entry count and optionally by an estimated
+// size in bytes. The oldest entries are evicted first and handed to the
+// eviction callback, if any, so anything an entry still owns (e.g. a
+// pooled entity) can be released.
+pub struct BoundedHistory<T> {
+    items: VecDeque<T>,
+    max_len: usize,
+    byte_budget: Option<(usize, fn(&T) -> usize)>,
+    bytes: usize,
+    on_evict: Option<EvictionCallback<T>>,
+}
+
+// Current and maximum size, for the debug overlay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryUsage {
+    pub len: usize,
+    pub max_len: usize,
+    // Estimated and maximum bytes, when there's a byte budget
+    pub bytes: Option<(usize, usize)>,
+}
+
+impl<T> BoundedHistory<T> {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            items: VecDeque::new(),
+            max_len,
+            byte_budget: None,
+            bytes: 0,
+            on_evict: None,
+        }
+    }
+
+    pub fn with_byte_budget(mut self, max_bytes: usize, estimate: fn(&T) -> usize) -> Self {
+        self.bytes = self.items.iter().map(estimate).sum();
+        self.byte_budget = Some((max_bytes, estimate));
+        self
+    }
+
+    // Called once for every entry pushed out by the caps or by `clear`.
+    // Entries taken back with `pop` belong to the caller instead.
+    pub fn on_evict(mut self, callback: impl FnMut(T) + Send + Sync + 'static) -> Self {
+        self.on_evict = Some(Box::new(callback));
+        self
+    }
+
+    // The newest entry is always kept, even if it alone is over budget
+    pub fn push(&mut self, item: T) {
+        if let Some((_, estimate)) = self.byte_budget {
+            self.bytes += estimate(&item);
+        }
+        self.items.push_back(item);
+
+        while self.items.len() > 1 && self.over_budget() {
+            if let Some(oldest) = self.items.pop_front() {
+                if let Some((_, estimate)) = self.byte_budget {
+                    self.bytes -= estimate(&oldest);
+                }
+                self.evict(oldest);
+            }
+        }
+    }
+
+    // Takes back the newest entry
+    pub fn pop(&mut self) -> Option<T> {
+        let item = self.items.pop_back()?;
+        if let Some((_, estimate)) = self.byte_budget {
+            self.bytes -= estimate(&item);
+        }
+        Some(item)
+    }
+
+    pub fn clear(&mut self) {
+        self.bytes = 0;
+        while let Some(oldest) = self.items.pop_front() {
+            self.evict(oldest);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn usage(&self) -> HistoryUsage {
+        HistoryUsage {
+            len: self.items.len(),
+            max_len: self.max_len,
+            bytes: self.byte_budget.map(|(max_bytes, _)| (self.bytes, max_bytes)),
+        }
+    }
+
+    // Oldest first
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + ExactSizeIterator {
+        self.items.iter()
+    }
+
+    fn evict(&mut self, item: T) {
+        if let Some(on_evict) = &mut self.on_evict {
+            on_evict(item);
+        }
+    }
+
+    fn over_budget(&self) -> bool {
+        self.items.len() > self.max_len
+            || self.byte_budget.is_some_and(|(max_bytes, _)| self.bytes > max_bytes)
+    }
+}
+
+// Callbacks can't be printed, so this shows the entries and the caps
+impl<T: fmt::Debug> fmt::Debug for BoundedHistory<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BoundedHistory")
+            .field("items", &self.items)
+            .field("usage", &self.usage())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    type Evicted<T> = Arc<Mutex<Vec<T>>>;
+
+    // Records evicted entries so each push can check what it pushed out
+    fn recording<T: Send + 'static>(history: BoundedHistory<T>) -> (BoundedHistory<T>, Evicted<T>) {
+        let evicted = Evicted::default();
+        let sink = evicted.clone();
+        (history.on_evict(move |item| sink.lock().unwrap().push(item)), evicted)
+    }
+
+    fn take<T>(evicted: &Evicted<T>) -> Vec<T> {
+        std::mem::take(&mut *evicted.lock().unwrap())
+    }
+
+    fn contents(history: &BoundedHistory<String>) -> Vec<&str> {
+        history.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn evicts_oldest_first_when_over_length() {
+        let (mut history, evicted) = recording(BoundedHistory::new(3));
+        for item in ["a", "b", "c"] {
+            history.push(item.to_string());
+        }
+        assert!(take(&evicted).is_empty());
+
+        history.push("d".to_string());
+        assert_eq!(take(&evicted), ["a"]);
+        history.push("e".to_string());
+        assert_eq!(take(&evicted), ["b"]);
+        assert_eq!(contents(&history), ["c", "d", "e"]);
+    }
+
+    #[test]
+    fn evicts_oldest_first_when_over_byte_budget() {
+        let history = BoundedHistory::new(100).with_byte_budget(10, |item: &String| item.len());
+        let (mut history, evicted) = recording(history);
+        history.push("aaaa".to_string());
+        history.push("bbbb".to_string());
+        assert!(take(&evicted).is_empty());
+
+        // 4 + 4 + 6 = 14 bytes, so the oldest has to go
+        history.push("cccccc".to_string());
+        assert_eq!(take(&evicted), ["aaaa"]);
+        assert_eq!(contents(&history), ["bbbb", "cccccc"]);
+
+        // 4 + 6 + 9 = 19 bytes: both older entries go, oldest first
+        history.push("ddddddddd".to_string());
+        assert_eq!(take(&evicted), ["bbbb", "cccccc"]);
+        assert_eq!(contents(&history), ["ddddddddd"]);
+    }
+
+    #[test]
+    fn keeps_newest_entry_even_over_byte_budget() {
+        let history = BoundedHistory::new(100).with_byte_budget(4, |item: &String| item.len());
+        let (mut history, evicted) = recording(history);
+        history.push("aa".to_string());
+
+        history.push("way over budget".to_string());
+        assert_eq!(take(&evicted), ["aa"]);
+        assert_eq!(contents(&history), ["way over budget"]);
+
+        // Budget is back under the limit once the big entry is replaced
+        history.push("b".to_string());
+        assert_eq!(take(&evicted), ["way over budget"]);
+        history.push("c".to_string());
+        assert!(take(&evicted).is_empty());
+        assert_eq!(contents(&history), ["b", "c"]);
+    }
+
+    #[test]
+    fn pop_and_clear_give_back_their_bytes() {
+        let history = BoundedHistory::new(100).with_byte_budget(10, |item: &String| item.len());
+        let (mut history, evicted) = recording(history);
+        history.push("aaaaa".to_string());
+        history.push("bbbbb".to_string());
+
+        // Full at exactly 10 bytes; popping frees room for another 5
+        assert_eq!(history.pop().as_deref(), Some("bbbbb"));
+        history.push("ccccc".to_string());
+        assert!(take(&evicted).is_empty());
+        assert_eq!(contents(&history), ["aaaaa", "ccccc"]);
+
+        history.clear();
+        assert_eq!(take(&evicted), ["aaaaa", "ccccc"]);
+        assert_eq!(history.pop(), None);
+        history.push("dddddddddd".to_string());
+        assert!(take(&evicted).is_empty());
+        assert_eq!(history.len(), 1);
+    }
+
+    #[test]
+    fn byte_budget_counts_entries_pushed_before_it() {
+        let mut history = BoundedHistory::new(100);
+        history.push("aaaaaa".to_string());
+        let history = history.with_byte_budget(10, |item: &String| item.len());
+        let (mut history, evicted) = recording(history);
+
+        history.push("bbbbbb".to_string());
+        assert_eq!(take(&evicted), ["aaaaaa"]);
+    }
+
+    #[test]
+    fn usage_reports_current_and_maximum_size() {
+        let mut history = BoundedHistory::new(3);
+        history.push("abc".to_string());
+        assert_eq!(history.usage(), HistoryUsage { len: 1, max_len: 3, bytes: None });
+
+        let mut history = history.with_byte_budget(10, |item: &String| item.len());
+        history.push("de".to_string());
+        let usage = HistoryUsage { len: 2, max_len: 3, bytes: Some((5, 10)) };
+        assert_eq!(history.usage(), usage);
+    }
+
+    #[test]
+    fn eviction_callback_fires_once_per_evicted_item() {
+        let history = BoundedHistory::new(5).with_byte_budget(20, |item: &usize| item % 7);
+        let (mut history, evicted) = recording(history);
+        let mut popped = Vec::new();
+        for item in 0..200 {
+            history.push(item);
+            if item % 11 == 0 {
+                popped.extend(history.pop());
+            }
+        }
+        let kept = history.iter().copied().collect::<Vec<_>>();
+        history.clear();
+
+        let mut evicted = take(&evicted);
+        assert_eq!(evicted[evicted.len() - kept.len()..], kept);
+        // Every item went exactly one way: evicted or popped
+        evicted.extend(popped);
+        evicted.sort_unstable();
+        assert_eq!(evicted, (0..200).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,324 @@
+use bevy::{input::mouse::MouseWheel, prelude::*};
+
+use crate::{slice::SliceView, voxel::BlockType};
+
+
+const SLOT_SIZE: f32 = 44.0;
+const SELECTED_BORDER: Color = Color::WHITE;
+const UNSELECTED_BORDER: Color = Color::srgba(0.0, 0.0, 0.0, 0.6);
+const MAX_RECENT: usize = 10;
+// How long the block name stays up after switching, then how long it fades
+const NAME_SHOWN: f32 = 1.0;
+const NAME_FADE: f32 = 0.5;
+
+const SLOT_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+#[derive(Debug, Resource)]
+pub struct HotbarSettings {
+    // Held while scrolling to cycle recent blocks instead of the whole bar
+    pub recent_modifier: KeyCode,
+}
+
+impl Default for HotbarSettings {
+    fn default() -> Self {
+        Self {
+            recent_modifier: KeyCode::AltLeft,
+        }
+    }
+}
+
+// Block type placed by left click
+#[derive(Debug, Resource)]
+pub struct SelectedBlock(pub BlockType);
+
+impl Default for SelectedBlock {
+    fn default() -> Self {
+        Self(BlockType::Stone)
+    }
+}
+
+// Block type in each slot of the bar, and the slot last picked by its
+// number or by scrolling. The catalog reassigns the current slot.
+#[derive(Debug, Resource)]
+pub struct Hotbar {
+    pub slots: Vec<BlockType>,
+    pub current: usize,
+}
+
+impl Default for Hotbar {
+    fn default() -> Self {
+        Self {
+            slots: BlockType::ALL.to_vec(),
+            current: 0,
+        }
+    }
+}
+
+impl Hotbar {
+    pub fn assign(&mut self, block: BlockType) {
+        let current = self.current;
+        self.slots[current] = block;
+    }
+
+    // The current slot if it holds `block`, otherwise the first that does
+    fn slot_of(&self, block: BlockType) -> Option<usize> {
+        if self.slots[self.current] == block {
+            return Some(self.current);
+        }
+        self.slots.iter().position(|slot| *slot == block)
+    }
+}
+
+// Distinct block types placed lately, most recent first. Scrolling with
+// the recent modifier held cycles through these instead of the whole bar.
+#[derive(Debug, Default, Resource)]
+pub struct RecentBlocks(Vec<BlockType>);
+
+impl RecentBlocks {
+    pub fn record(&mut self, block: BlockType) {
+        self.0.retain(|recent| *recent != block);
+        self.0.insert(0, block);
+        self.0.truncate(MAX_RECENT);
+    }
+}
+
+// Index into `Hotbar::slots`
+#[derive(Component)]
+pub struct HotbarSlot(usize);
+
+#[derive(Component)]
+pub struct BlockNameLabel;
+
+pub fn setup_hotbar(mut commands: Commands, hotbar: Res<Hotbar>) {
+    commands
+        .spawn((
+            Name::new("Block Name"),
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                bottom: Val::Px(SLOT_SIZE + 20.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+        ))
+        .with_child((
+            BlockNameLabel,
+            Text::default(),
+            TextColor(Color::NONE),
+        ));
+
+    commands
+        .spawn((
+            Name::new("Hotbar"),
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                bottom: Val::Px(12.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            for (index, block) in hotbar.slots.iter().enumerate() {
+                parent
+                    .spawn((
+                        HotbarSlot(index),
+                        Node {
+                            width: Val::Px(SLOT_SIZE),
+                            height: Val::Px(SLOT_SIZE),
+                            margin: UiRect::horizontal(Val::Px(2.0)),
+                            border: UiRect::all(Val::Px(3.0)),
+                            padding: UiRect::left(Val::Px(2.0)),
+                            ..default()
+                        },
+                        BackgroundColor(block.color()),
+                        BorderColor(UNSELECTED_BORDER),
+                    ))
+                    .with_child((
+                        Text::new((index + 1).to_string()),
+                        TextFont {
+                            font_size: 12.0,
+                            ..default()
+                        },
+                        TextColor(Color::WHITE),
+                    ));
+            }
+        });
+}
+
+pub fn select_block(
+    settings: Res<HotbarSettings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    slice: Res<SliceView>,
+    recent: Res<RecentBlocks>,
+    mut hotbar: ResMut<Hotbar>,
+    mut selected: ResMut<SelectedBlock>,
+) {
+    let mut block = selected.0;
+    for (slot, key) in SLOT_KEYS.iter().enumerate().take(hotbar.slots.len()) {
+        if keyboard.just_pressed(*key) {
+            hotbar.current = slot;
+            block = hotbar.slots[slot];
+        }
+    }
+
+    // Scrolling down moves right along the bar, wrapping at either end.
+    // Slice view takes the wheel over while it's on.
+    let steps = mouse_wheel
+        .read()
+        .filter(|_| !slice.active)
+        .map(|event| if event.y < 0.0 { 1 } else if event.y > 0.0 { -1 } else { 0 })
+        .sum::<i32>();
+
+    // Only an actual scroll switches lists, so holding the modifier alone
+    // never changes the selection
+    if steps != 0 {
+        if keyboard.pressed(settings.recent_modifier) && !recent.0.is_empty() {
+            // Starts from the newest when the current block isn't a recent one
+            let index = recent
+                .0
+                .iter()
+                .position(|choice| *choice == block)
+                .map_or(0, |position| position as i32 + steps);
+            block = recent.0[index.rem_euclid(recent.0.len() as i32) as usize];
+        } else {
+            let slots = hotbar.slots.len() as i32;
+            hotbar.current = (hotbar.current as i32 + steps).rem_euclid(slots) as usize;
+            block = hotbar.slots[hotbar.current];
+        }
+    }
+
+    if selected.0 != block {
+        selected.0 = block;
+    }
+}
+
+pub fn update_hotbar(
+    hotbar: Res<Hotbar>,
+    selected: Res<SelectedBlock>,
+    mut slot_query: Query<(&HotbarSlot, &mut BackgroundColor, &mut BorderColor)>,
+    mut label_query: Query<(&mut Text, &mut TextColor), With<BlockNameLabel>>,
+    time: Res<Time>,
+    mut changed_at: Local<Option<f32>>,
+) {
+    let (mut label_text, mut label_color) = label_query.single_mut();
+
+    // Flash the name of the block after each switch, but not at startup
+    if let Some(changed_at) = *changed_at {
+        let shown = time.elapsed_secs() - changed_at;
+        let alpha = 1.0 - ((shown - NAME_SHOWN) / NAME_FADE).clamp(0.0, 1.0);
+        label_color.0 = Color::WHITE.with_alpha(alpha);
+    }
+
+    if !selected.is_changed() && !hotbar.is_changed() {
+        return;
+    }
+
+    if selected.is_changed() && !selected.is_added() {
+        label_text.0 = selected.0.name().to_string();
+        *changed_at = Some(time.elapsed_secs());
+    }
+
+    let highlighted = hotbar.slot_of(selected.0);
+    for (slot, mut background, mut border) in &mut slot_query {
+        background.0 = hotbar.slots[slot.0].color();
+        border.0 = if Some(slot.0) == highlighted {
+            SELECTED_BORDER
+        } else {
+            UNSELECTED_BORDER
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{ecs::system::RunSystemOnce, input::mouse::MouseScrollUnit};
+
+    use super::*;
+
+    fn hotbar_world() -> World {
+        let mut world = World::new();
+        world.init_resource::<HotbarSettings>();
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<Events<MouseWheel>>();
+        world.init_resource::<SliceView>();
+        world.init_resource::<RecentBlocks>();
+        world.init_resource::<Hotbar>();
+        world.init_resource::<SelectedBlock>();
+        world
+    }
+
+    // One frame with `keys` held and the wheel turned `y` notches
+    fn frame(world: &mut World, keys: &[KeyCode], y: f32) {
+        let mut keyboard = world.resource_mut::<ButtonInput<KeyCode>>();
+        keyboard.reset_all();
+        for &key in keys {
+            keyboard.press(key);
+        }
+        if y != 0.0 {
+            world.send_event(MouseWheel {
+                unit: MouseScrollUnit::Line,
+                x: 0.0,
+                y,
+                window: Entity::PLACEHOLDER,
+            });
+        }
+        world.run_system_once(select_block).unwrap();
+        world.resource_mut::<Events<MouseWheel>>().clear();
+    }
+
+    fn selected(world: &World) -> BlockType {
+        world.resource::<SelectedBlock>().0
+    }
+
+    #[test]
+    fn scrolling_moves_along_the_bar() {
+        let mut world = hotbar_world();
+        frame(&mut world, &[], -1.0);
+        assert_eq!(selected(&world), BlockType::Sandstone);
+        frame(&mut world, &[], 1.0);
+        frame(&mut world, &[], 1.0);
+        assert_eq!(selected(&world), BlockType::Glass);
+        assert_eq!(world.resource::<Hotbar>().current, 5);
+    }
+
+    #[test]
+    fn alt_scroll_cycles_recent_blocks() {
+        let mut world = hotbar_world();
+        let mut recent = world.resource_mut::<RecentBlocks>();
+        recent.record(BlockType::Wood);
+        recent.record(BlockType::Glass);
+
+        // Holding Alt alone, e.g. with other keys, changes nothing
+        frame(&mut world, &[KeyCode::AltLeft], 0.0);
+        assert_eq!(selected(&world), BlockType::Stone);
+
+        frame(&mut world, &[KeyCode::AltLeft], -1.0);
+        assert_eq!(selected(&world), BlockType::Glass);
+        frame(&mut world, &[KeyCode::AltLeft], -1.0);
+        assert_eq!(selected(&world), BlockType::Wood);
+        // The bar position is left where it was
+        assert_eq!(world.resource::<Hotbar>().current, 0);
+    }
+
+    #[test]
+    fn number_keys_pick_assigned_slots() {
+        let mut world = hotbar_world();
+        world.resource_mut::<Hotbar>().slots[2] = BlockType::Glass;
+
+        frame(&mut world, &[KeyCode::Digit3], 0.0);
+        assert_eq!(selected(&world), BlockType::Glass);
+        assert_eq!(world.resource::<Hotbar>().current, 2);
+    }
+}
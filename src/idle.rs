@@ -0,0 +1,48 @@
+use std::time::Duration;
+use bevy::{
+    input::mouse::MouseMotion,
+    prelude::*,
+    window::WindowFocused,
+    winit::{UpdateMode, WinitSettings},
+};
+
+
+#[derive(Debug, Resource)]
+pub struct IdleSettings {
+    // Streamers often want full rendering while the window is in the background
+    pub throttle_when_unfocused: bool,
+    pub unfocused_fps: f32,
+}
+
+impl Default for IdleSettings {
+    fn default() -> Self {
+        Self {
+            throttle_when_unfocused: true,
+            unfocused_fps: 10.0,
+        }
+    }
+}
+
+pub fn apply_idle_settings(settings: Res<IdleSettings>, mut winit_settings: ResMut<WinitSettings>) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    winit_settings.unfocused_mode = if settings.throttle_when_unfocused {
+        let frame_time = 1.0 / settings.unfocused_fps.max(1.0);
+        UpdateMode::reactive_low_power(Duration::from_secs_f32(frame_time))
+    } else {
+        UpdateMode::Continuous
+    };
+}
+
+// Mouse motion that piled up while the window was in the background would
+// otherwise spin the camera on the first frame back
+pub fn drain_input_on_focus(
+    mut focus_events: EventReader<WindowFocused>,
+    mut mouse_motion: ResMut<Events<MouseMotion>>,
+) {
+    if focus_events.read().any(|event| event.focused) {
+        mouse_motion.clear();
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use bevy::prelude::*;
+
+use crate::{
+    chat::{ChatCommand, ChatCommandRegistry, ChatState},
+    terrain::{lattice_value, surface_height, TerrainSettings},
+    voxel::{BlockType, CHUNK_EDGE},
+};
+
+
+// Keeps landmark rolls apart from the terrain noise on the same seed
+const LANDMARK_SEED: u64 = 0x1A4D_0000_0000_0000;
+
+// Rough terrain classes by surface height, for landmarks to pick where
+// they stand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Biome {
+    Lowlands,
+    Highlands,
+}
+
+impl Biome {
+    pub fn at(settings: &TerrainSettings, height: i32) -> Biome {
+        if height * 2 < settings.min_height + settings.max_height {
+            Biome::Lowlands
+        } else {
+            Biome::Highlands
+        }
+    }
+}
+
+// Blocks relative to an origin on the ground at the structure's centre
+#[derive(Debug, Clone)]
+pub struct StructureTemplate {
+    pub blocks: Vec<(IVec3, BlockType)>,
+}
+
+impl StructureTemplate {
+    pub fn place(&self, origin: IVec3) -> impl Iterator<Item = (IVec3, BlockType)> + '_ {
+        self.blocks.iter().map(move |(offset, block)| (origin + *offset, *block))
+    }
+
+    // A square of crumbling stone walls, standing highest at the corners
+    pub fn ruin() -> Self {
+        let mut blocks = Vec::new();
+        for x in -2..=2_i32 {
+            for z in -2..=2_i32 {
+                if x.abs() != 2 && z.abs() != 2 {
+                    continue;
+                }
+                let height = if x.abs() == z.abs() { 3 } else { 1 + (x + z).rem_euclid(2) };
+                blocks.extend((0..height).map(|y| (IVec3::new(x, y, z), BlockType::Stone)));
+            }
+        }
+        Self { blocks }
+    }
+
+    // Four wooden posts holding up a lookout floor
+    pub fn watchtower() -> Self {
+        let mut blocks = Vec::new();
+        for y in 0..6 {
+            for x in -1..=1_i32 {
+                for z in -1..=1_i32 {
+                    if y == 5 || (x.abs() == 1 && z.abs() == 1) {
+                        blocks.push((IVec3::new(x, y, z), BlockType::Wood));
+                    }
+                }
+            }
+        }
+        Self { blocks }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Landmark {
+    pub name: String,
+    pub template: StructureTemplate,
+    pub biome_filter: Vec<Biome>,
+    // Chance of standing in any one chunk column
+    pub frequency: f32,
+}
+
+#[derive(Debug, Resource)]
+pub struct LandmarkRegistry {
+    pub landmarks: Vec<Landmark>,
+}
+
+impl Default for LandmarkRegistry {
+    fn default() -> Self {
+        Self {
+            landmarks: vec![
+                Landmark {
+                    name: "ruin".to_string(),
+                    template: StructureTemplate::ruin(),
+                    biome_filter: vec![Biome::Lowlands],
+                    frequency: 0.15,
+                },
+                Landmark {
+                    name: "watchtower".to_string(),
+                    template: StructureTemplate::watchtower(),
+                    biome_filter: vec![Biome::Highlands],
+                    frequency: 0.1,
+                },
+            ],
+        }
+    }
+}
+
+impl LandmarkRegistry {
+    // Where the landmark in this chunk column stands, if there is one. At
+    // most one per column: the first registered that rolls low enough.
+    pub fn landmark_in(
+        &self,
+        column: IVec2,
+        settings: &TerrainSettings,
+    ) -> Option<(IVec3, &Landmark)> {
+        let centre = column * CHUNK_EDGE + IVec2::splat(CHUNK_EDGE / 2);
+        let height = surface_height(settings, centre.x, centre.y);
+        let biome = Biome::at(settings, height);
+
+        let (_, landmark) = self.landmarks.iter().enumerate().find(|(index, landmark)| {
+            let seed = settings.seed ^ LANDMARK_SEED.wrapping_add(*index as u64);
+            landmark.biome_filter.contains(&biome)
+                && lattice_value(seed, column.x, column.y) < landmark.frequency
+        })?;
+        Some((IVec3::new(centre.x, height + 1, centre.y), landmark))
+    }
+}
+
+// Landmarks placed so far, by origin
+#[derive(Debug, Default, Resource)]
+pub struct WorldMap {
+    pub landmarks: HashMap<IVec3, String>,
+}
+
+impl WorldMap {
+    pub fn nearest(&self, name: &str, position: Vec3) -> Option<IVec3> {
+        self.landmarks
+            .iter()
+            .filter(|(_, landmark)| *landmark == name)
+            .map(|(origin, _)| *origin)
+            .min_by(|a, b| {
+                let distance = |origin: &IVec3| origin.as_vec3().distance_squared(position);
+                distance(a).total_cmp(&distance(b))
+            })
+    }
+}
+
+pub fn register_landmark_commands(mut registry: ResMut<ChatCommandRegistry>) {
+    registry.register(&["locate"]);
+}
+
+pub fn landmark_commands(
+    camera_query: Query<&Transform, With<Camera>>,
+    mut chat_commands: EventReader<ChatCommand>,
+    mut chat: ResMut<ChatState>,
+    map: Res<WorldMap>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+
+    for command in chat_commands.read() {
+        match (command.name.as_str(), command.args.as_slice()) {
+            ("locate", [kind, name]) if kind == "landmark" => {
+                let position = camera_query.single().translation;
+                match map.nearest(name, position) {
+                    Some(origin) => {
+                        let distance = origin.as_vec3().distance(position);
+                        chat.push_system(
+                            format!(
+                                "Nearest {name}: {} {} {} ({distance:.0} blocks away)",
+                                origin.x, origin.y, origin.z
+                            ),
+                            now,
+                        );
+                    }
+                    None => chat.push_system(format!("No {name} found yet"), now),
+                }
+            }
+            ("locate", _) => chat.push_system("Usage: /locate landmark <name>", now),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    // Landmarks across a patch of columns around the origin
+    fn landmarks(registry: &LandmarkRegistry, settings: &TerrainSettings) -> Vec<(IVec3, String)> {
+        let mut found = Vec::new();
+        for x in -8..8 {
+            for z in -8..8 {
+                if let Some((origin, landmark)) = registry.landmark_in(IVec2::new(x, z), settings) {
+                    found.push((origin, landmark.name.clone()));
+                }
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn landmarks_stand_on_the_surface_where_their_biome_is() {
+        let settings = TerrainSettings { seed: 11, ..default() };
+        let registry = LandmarkRegistry::default();
+        let found = landmarks(&registry, &settings);
+        assert!(found.iter().any(|(_, name)| name == "ruin"));
+        assert!(found.iter().any(|(_, name)| name == "watchtower"));
+
+        for (origin, name) in &found {
+            let height = surface_height(&settings, origin.x, origin.z);
+            assert_eq!(origin.y, height + 1);
+            assert_eq!(origin.xz().rem_euclid(IVec2::splat(CHUNK_EDGE)), IVec2::splat(8));
+            let expected = if name == "ruin" { Biome::Lowlands } else { Biome::Highlands };
+            assert_eq!(Biome::at(&settings, height), expected, "{name} at {origin}");
+        }
+
+        // The same seed always puts them in the same places
+        assert_eq!(landmarks(&registry, &settings), found);
+    }
+
+    #[test]
+    fn frequency_is_the_chance_per_column() {
+        let settings = TerrainSettings { seed: 3, ..default() };
+        let everywhere = |frequency| LandmarkRegistry {
+            landmarks: vec![Landmark {
+                name: "cairn".to_string(),
+                template: StructureTemplate { blocks: vec![(IVec3::ZERO, BlockType::Stone)] },
+                biome_filter: vec![Biome::Lowlands, Biome::Highlands],
+                frequency,
+            }],
+        };
+        assert_eq!(landmarks(&everywhere(1.0), &settings).len(), 256);
+        assert!(landmarks(&everywhere(0.0), &settings).is_empty());
+        let some = landmarks(&everywhere(0.25), &settings).len();
+        assert!((32..=96).contains(&some), "{some} of 256");
+    }
+
+    #[test]
+    fn templates_are_placed_around_their_origin() {
+        let ruin = StructureTemplate::ruin();
+        let origin = IVec3::new(40, 12, -8);
+        let placed = ruin.place(origin).collect::<Vec<_>>();
+        assert_eq!(placed.len(), ruin.blocks.len());
+        assert!(placed.contains(&(origin + IVec3::new(2, 2, 2), BlockType::Stone)));
+        assert!(placed.iter().all(|(cell, _)| cell.y >= origin.y));
+        assert!(!placed.iter().any(|(cell, _)| *cell == origin));
+    }
+
+    fn locate(map: WorldMap, args: &[&str]) -> String {
+        let mut world = World::new();
+        world.insert_resource(map);
+        world.init_resource::<ChatState>();
+        world.init_resource::<Events<ChatCommand>>();
+        world.init_resource::<Time>();
+        world.spawn((Camera::default(), Transform::from_xyz(0.0, 10.0, 0.0)));
+        world.send_event(ChatCommand {
+            name: "locate".to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        });
+        world.run_system_once(landmark_commands).unwrap();
+        let chat = world.resource::<ChatState>();
+        chat.messages.iter().next_back().unwrap().text.clone()
+    }
+
+    #[test]
+    fn locate_finds_the_nearest_of_a_kind() {
+        let mut map = WorldMap::default();
+        map.landmarks.insert(IVec3::new(100, 10, 0), "ruin".to_string());
+        map.landmarks.insert(IVec3::new(-30, 10, 40), "ruin".to_string());
+        map.landmarks.insert(IVec3::new(5, 10, 0), "watchtower".to_string());
+
+        assert_eq!(locate(map, &["landmark", "ruin"]), "Nearest ruin: -30 10 40 (50 blocks away)");
+        assert_eq!(locate(WorldMap::default(), &["landmark", "ruin"]), "No ruin found yet");
+        assert_eq!(locate(WorldMap::default(), &["ruin"]), "Usage: /locate landmark <name>");
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use bevy::prelude::*;
+
+use crate::{
+    chat::{ChatCommand, ChatCommandRegistry, ChatState},
+    collision::{player_obstructed, CollisionShape},
+    voxel::VoxelWorld,
+};
+
+
+const MAX_NAME_LEN: usize = 24;
+const FADE_DURATION: f32 = 0.25;
+// How far above a built-over location we look for free space
+const MAX_SAFE_SEARCH: i32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub position: Vec3,
+    pub yaw: f32,
+}
+
+// Sorted so `/marks` lists locations alphabetically
+#[derive(Debug, Default, Resource)]
+pub struct NamedLocations {
+    pub locations: BTreeMap<String, Location>,
+}
+
+// A warp fades to black, snaps the camera at full black, then fades back in
+#[derive(Debug, Default, Resource)]
+pub struct Warp {
+    target: Option<Location>,
+    elapsed: f32,
+}
+
+#[derive(Component)]
+pub struct WarpFade;
+
+pub fn register_location_commands(mut registry: ResMut<ChatCommandRegistry>) {
+    registry.register(&["mark", "unmark", "warp", "marks"]);
+}
+
+pub fn setup_warp_fade(mut commands: Commands) {
+    commands.spawn((
+        Name::new("Warp Fade"),
+        WarpFade,
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.0)),
+    ));
+}
+
+pub fn location_commands(
+    camera_query: Query<&Transform, With<Camera>>,
+    mut chat_commands: EventReader<ChatCommand>,
+    mut chat: ResMut<ChatState>,
+    mut locations: ResMut<NamedLocations>,
+    mut warp: ResMut<Warp>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_secs();
+
+    for command in chat_commands.read() {
+        match (command.name.as_str(), command.args.as_slice()) {
+            ("mark", [name]) => {
+                if let Err(reason) = validate_name(name) {
+                    chat.push_system(reason, now);
+                } else if locations.locations.contains_key(name) {
+                    chat.push_system(format!("Location '{name}' already exists"), now);
+                } else {
+                    let camera = camera_query.single();
+                    let (yaw, _, _) = camera.rotation.to_euler(EulerRot::YXZ);
+                    locations.locations.insert(
+                        name.clone(),
+                        Location {
+                            position: camera.translation,
+                            yaw,
+                        },
+                    );
+                    chat.push_system(format!("Marked '{name}'"), now);
+                }
+            }
+            ("mark", _) => chat.push_system("Usage: /mark <name>", now),
+            ("unmark", [name]) => match locations.locations.remove(name) {
+                Some(_) => chat.push_system(format!("Removed '{name}'"), now),
+                None => chat.push_system(format!("No location named '{name}'"), now),
+            },
+            ("unmark", _) => chat.push_system("Usage: /unmark <name>", now),
+            ("warp", [name]) => match locations.locations.get(name) {
+                Some(location) if warp.target.is_none() => {
+                    warp.target = Some(*location);
+                    warp.elapsed = 0.0;
+                }
+                Some(_) => chat.push_system("Already warping", now),
+                None => chat.push_system(format!("No location named '{name}'"), now),
+            },
+            ("warp", _) => chat.push_system("Usage: /warp <name>", now),
+            ("marks", _) if locations.locations.is_empty() => {
+                chat.push_system("No marked locations", now)
+            }
+            ("marks", _) => {
+                for (name, location) in &locations.locations {
+                    let p = location.position;
+                    chat.push_system(format!("{name}: {:.0} {:.0} {:.0}", p.x, p.y, p.z), now);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(format!("Location names are limited to {MAX_NAME_LEN} characters"));
+    }
+    if !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        return Err("Location names may only contain letters, digits, '-' and '_'".to_string());
+    }
+    Ok(())
+}
+
+pub fn run_warp(
+    mut camera_query: Query<(&mut Transform, &CollisionShape), With<Camera>>,
+    world: Res<VoxelWorld>,
+    mut fade_query: Query<&mut BackgroundColor, With<WarpFade>>,
+    mut warp: ResMut<Warp>,
+    time: Res<Time>,
+) {
+    let Some(target) = warp.target else {
+        return;
+    };
+
+    let before = warp.elapsed;
+    warp.elapsed += time.delta_secs();
+
+    // Snap while the screen is fully black
+    if before < FADE_DURATION && warp.elapsed >= FADE_DURATION {
+        let (mut camera, shape) = camera_query.single_mut();
+        let (_, pitch, _) = camera.rotation.to_euler(EulerRot::YXZ);
+        camera.translation = safe_position(target.position, *shape, &world);
+        camera.rotation = Quat::from_euler(EulerRot::YXZ, target.yaw, pitch, 0.0);
+    }
+
+    let progress = warp.elapsed / FADE_DURATION;
+    let alpha = if progress < 1.0 { progress } else { 2.0 - progress };
+    fade_query.single_mut().0 = Color::BLACK.with_alpha(alpha.clamp(0.0, 1.0));
+
+    if progress >= 2.0 {
+        warp.target = None;
+    }
+}
+
+// Something may have been built over the mark since it was placed, so
+// step upwards a block at a time until the whole player box is clear.
+// Checking only the eye would leave the feet in a block, and walking mode
+// doesn't collide with blocks the box already overlaps.
+fn safe_position(position: Vec3, shape: CollisionShape, world: &VoxelWorld) -> Vec3 {
+    (0..=MAX_SAFE_SEARCH)
+        .map(|step| position + Vec3::Y * step as f32)
+        .find(|&eye| !player_obstructed(world, shape, eye))
+        .unwrap_or(position)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::voxel::BlockType;
+
+    fn world_with(cells: &[IVec3]) -> VoxelWorld {
+        let mut world = VoxelWorld::default();
+        for cell in cells {
+            world.set_block(*cell, BlockType::Stone);
+        }
+        world
+    }
+
+    #[test]
+    fn safe_position_keeps_a_clear_mark() {
+        let world = world_with(&[IVec3::new(0, 0, 0)]);
+        let eye = Vec3::new(0.5, 2.7, 0.5);
+        assert_eq!(safe_position(eye, CollisionShape::default(), &world), eye);
+    }
+
+    #[test]
+    fn safe_position_lifts_the_feet_out_of_a_block() {
+        // The eye's own cell is free, but the feet stand inside the block
+        let world = world_with(&[IVec3::new(0, 1, 0)]);
+        let eye = Vec3::new(0.5, 2.7, 0.5);
+        assert_eq!(safe_position(eye, CollisionShape::default(), &world), eye + Vec3::Y);
+    }
+
+    #[test]
+    fn safe_position_checks_the_width_of_the_box() {
+        // Next door, but within the box's half width of the eye
+        let world = world_with(&[IVec3::new(1, 2, 0)]);
+        let eye = Vec3::new(0.9, 2.7, 0.5);
+        assert_eq!(safe_position(eye, CollisionShape::default(), &world), eye + Vec3::Y * 2.0);
+    }
+
+    #[test]
+    fn safe_position_climbs_over_a_tower() {
+        let tower = (1..5).map(|y| IVec3::new(0, y, 0)).collect::<Vec<_>>();
+        let world = world_with(&tower);
+        let eye = Vec3::new(0.5, 2.7, 0.5);
+        // Feet just above the highest block
+        assert_eq!(safe_position(eye, CollisionShape::default(), &world), eye + Vec3::Y * 4.0);
+    }
+
+    #[test]
+    fn safe_position_makes_room_for_the_whole_shape() {
+        // Clear of the default box's head, but not of a taller one
+        let world = world_with(&[IVec3::new(0, 3, 0)]);
+        let eye = Vec3::new(0.5, 2.7, 0.5);
+        let tall = CollisionShape { height: 2.8, ..default() };
+        assert_eq!(safe_position(eye, CollisionShape::default(), &world), eye);
+        assert_eq!(safe_position(eye, tall, &world), eye + Vec3::Y * 3.0);
+    }
+
+    #[test]
+    fn names_are_limited_in_length_and_characters() {
+        assert!(validate_name("gate").is_ok());
+        assert!(validate_name("north-tower_2").is_ok());
+        assert!(validate_name(&"a".repeat(MAX_NAME_LEN)).is_ok());
+        assert!(validate_name(&"a".repeat(MAX_NAME_LEN + 1)).is_err());
+        assert!(validate_name("two words").is_err());
+        assert!(validate_name("gate!").is_err());
+    }
+
+    #[test]
+    fn marking_a_taken_name_keeps_the_first_location() {
+        let mut world = World::new();
+        world.init_resource::<Events<ChatCommand>>();
+        world.init_resource::<ChatState>();
+        world.init_resource::<NamedLocations>();
+        world.init_resource::<Warp>();
+        world.init_resource::<Time>();
+        let camera = world.spawn((Camera::default(), Transform::from_xyz(1.0, 2.0, 3.0))).id();
+
+        let mark = || ChatCommand {
+            name: "mark".to_string(),
+            args: vec!["gate".to_string()],
+        };
+        world.send_event(mark());
+        world.run_system_once(location_commands).unwrap();
+        world.get_mut::<Transform>(camera).unwrap().translation = Vec3::new(9.0, 9.0, 9.0);
+        world.send_event(mark());
+        world.run_system_once(location_commands).unwrap();
+
+        let locations = world.resource::<NamedLocations>();
+        assert_eq!(locations.locations.len(), 1);
+        assert_eq!(locations.locations["gate"].position, Vec3::new(1.0, 2.0, 3.0));
+        let last = world.resource::<ChatState>().messages.iter().last().unwrap();
+        assert_eq!(last.text, "Location 'gate' already exists");
+    }
+}
@@ -1,20 +1,112 @@
-use std::{f32::consts::FRAC_PI_2, ops::Range};
+use std::{collections::HashSet, f32::consts::FRAC_PI_2, ops::Range};
 use bevy::{
     input::mouse::MouseMotion, 
-    prelude::*, window::{CursorGrabMode, Window}
+    prelude::*,
 };
 
+mod benchmark;
+mod budget;
+mod catalog;
+mod chat;
+mod collision;
+mod cursor;
+mod debug_overlay;
+mod exit;
+mod flythrough;
+mod graphics;
+mod history;
+mod hotbar;
+mod idle;
+mod landmarks;
+mod locations;
+mod measure;
+mod render_health;
+mod save;
+mod settings;
+mod screenshot_mode;
+mod slice;
+mod spike_capture;
+mod targeting;
+mod terrain;
+mod undo;
+mod voxel;
+
+use collision::{CollisionShape, PlayerPhysics};
+use hotbar::{RecentBlocks, SelectedBlock};
+use targeting::{Line, LineLock, Target};
+use undo::{BlockEdit, EditHistory};
+use voxel::{BlockAssets, VoxelHit, VoxelWorld};
+
+
+const MAX_REACH: f32 = 10.0;
+
+
+
+#[derive(Debug, Event)]
+struct RemoveBlock(IVec3);
 
-const CHUNK_SIZE:i16 = 64; 
-const _CHUNK_SIZE_HALF:i16 = CHUNK_SIZE/2; 
-
+#[derive(Debug, Resource)]
+struct BuildSettings {
+    pub place_button: MouseButton,
+    pub remove_button: MouseButton,
+    // Corner-peek: aiming near the outer edge of a top face places beside
+    // the block instead of on top, for extending floors over a drop. Not on
+    // Alt, which scrolls recent blocks.
+    pub corner_assist_key: KeyCode,
+    pub corner_assist_always: bool,
+    pub corner_assist_edge: f32,
+    pub corner_assist_pitch: Range<f32>,
+    // Held to keep placing along the row started by the next block
+    pub axis_lock_key: KeyCode,
+    pub reach: f32,
+}
 
+impl Default for BuildSettings {
+    fn default() -> Self {
+        Self {
+            place_button: MouseButton::Left,
+            remove_button: MouseButton::Right,
+            corner_assist_key: KeyCode::KeyC,
+            corner_assist_always: false,
+            corner_assist_edge: 0.2,
+            corner_assist_pitch: -80f32.to_radians()..-10f32.to_radians(),
+            axis_lock_key: KeyCode::KeyX,
+            reach: MAX_REACH,
+        }
+    }
+}
 
 #[derive(Debug, Resource)]
 struct CameraSettings {
     pub speed: f32,
+    // `speed` is multiplied or divided by `speed_step` per press
+    pub speed_up_key: KeyCode,
+    pub speed_down_key: KeyCode,
+    pub speed_step: f32,
+    pub speed_range: Range<f32>,
     pub sensitivity: f32,
+    pub invert_y: bool,
     pub pitch_range: Range<f32>,
+    // Vertical field of view in degrees
+    pub fov: f32,
+    pub forward_key: KeyCode,
+    pub back_key: KeyCode,
+    pub left_key: KeyCode,
+    pub right_key: KeyCode,
+    // Jumps while walking, flies up otherwise
+    pub jump_key: KeyCode,
+    pub descend_key: KeyCode,
+    // Free-fly through blocks, as before collision existed
+    pub noclip: bool,
+    pub noclip_key: KeyCode,
+    // Walking adds gravity and jumping; otherwise the camera flies
+    pub walking: bool,
+    pub walking_key: KeyCode,
+    pub gravity: f32,
+    pub jump_speed: f32,
+    // Seconds for look and movement to catch up with input, for smooth
+    // footage; 0 turns it off
+    pub smoothing: f32,
 }
 
 impl Default for CameraSettings {
@@ -22,92 +114,329 @@ impl Default for CameraSettings {
         let pitch_limit = FRAC_PI_2 - 0.01;
         Self {
             speed: 5.0,
+            speed_up_key: KeyCode::BracketRight,
+            speed_down_key: KeyCode::BracketLeft,
+            speed_step: 1.25,
+            speed_range: 0.5..50.0,
             sensitivity: 0.003,
+            invert_y: false,
             pitch_range: -pitch_limit..pitch_limit,
+            fov: 45.0,
+            forward_key: KeyCode::KeyW,
+            back_key: KeyCode::KeyS,
+            left_key: KeyCode::KeyA,
+            right_key: KeyCode::KeyD,
+            jump_key: KeyCode::Space,
+            descend_key: KeyCode::ShiftLeft,
+            noclip: false,
+            noclip_key: KeyCode::KeyV,
+            walking: false,
+            walking_key: KeyCode::KeyG,
+            gravity: 25.0,
+            jump_speed: 8.0,
+            smoothing: 0.0,
         }
     }
 }
 
+// Where look and movement are easing towards while smoothing is on
+#[derive(Debug, Default, Resource)]
+struct CameraSmoothing {
+    // Yaw and pitch
+    look: Vec2,
+    look_target: Vec2,
+    velocity: Vec3,
+    // What this wrote last frame, to notice other systems turning the camera
+    rotation: Quat,
+}
+
+// Exponential approach that's independent of frame rate
+fn smoothing_blend(smoothing: f32, delta: f32) -> f32 {
+    if smoothing <= 0.0 {
+        1.0
+    } else {
+        1.0 - (-delta / smoothing).exp()
+    }
+}
+
 fn main() {
     App::new()
-        .add_plugins(DefaultPlugins)
+        .add_plugins((
+            // The exit prompt decides when a close request actually quits
+            DefaultPlugins.set(WindowPlugin {
+                close_when_requested: false,
+                ..default()
+            }),
+            graphics::antialiasing_plugin,
+        ))
         .init_resource::<CameraSettings>()
-        .add_systems(Startup, (setup, grab_cursor))
-        .add_systems(Update, player_movement)
-        .add_systems(Update, place_block)
+        .init_resource::<PlayerPhysics>()
+        .init_resource::<CameraSmoothing>()
+        .init_resource::<BuildSettings>()
+        .init_resource::<cursor::CursorSettings>()
+        .init_resource::<cursor::GrabState>()
+        .init_resource::<idle::IdleSettings>()
+        .init_resource::<flythrough::FlythroughSettings>()
+        .init_resource::<flythrough::Flythrough>()
+        .init_resource::<VoxelWorld>()
+        .init_resource::<budget::FrameBudget>()
+        .init_resource::<debug_overlay::DebugOverlaySettings>()
+        .init_resource::<debug_overlay::DebugOverlay>()
+        .init_resource::<voxel::ChunkEntities>()
+        .init_resource::<Target>()
+        .init_resource::<LineLock>()
+        .init_resource::<hotbar::HotbarSettings>()
+        .init_resource::<SelectedBlock>()
+        .init_resource::<RecentBlocks>()
+        .init_resource::<hotbar::Hotbar>()
+        .init_resource::<catalog::CatalogSettings>()
+        .init_resource::<catalog::Catalog>()
+        .init_resource::<undo::UndoSettings>()
+        .init_resource::<EditHistory>()
+        .add_event::<RemoveBlock>()
+        .init_resource::<chat::ChatSettings>()
+        .init_resource::<chat::ChatState>()
+        .init_resource::<chat::ChatCommandRegistry>()
+        .add_event::<chat::ChatCommand>()
+        .init_resource::<locations::NamedLocations>()
+        .init_resource::<locations::Warp>()
+        .init_resource::<benchmark::Benchmark>()
+        .init_resource::<save::UnsavedChanges>()
+        .init_resource::<exit::ExitPrompt>()
+        .insert_resource(save::SaveSettings::from_args())
+        .insert_resource(terrain::TerrainSettings::from_args())
+        .init_resource::<terrain::TerrainQueue>()
+        .init_resource::<landmarks::LandmarkRegistry>()
+        .init_resource::<landmarks::WorldMap>()
+        .init_resource::<measure::MeasureSettings>()
+        .init_resource::<measure::MeasureTool>()
+        .init_resource::<graphics::GraphicsSettings>()
+        .init_resource::<graphics::AutoQuality>()
+        .init_resource::<render_health::RenderHealthSettings>()
+        .init_resource::<spike_capture::SpikeCaptureSettings>()
+        .init_resource::<spike_capture::SpikeCapture>()
+        .init_resource::<render_health::RenderHealth>()
+        .init_resource::<screenshot_mode::ScreenshotModeSettings>()
+        .init_resource::<screenshot_mode::ScreenshotMode>()
+        .init_resource::<slice::SliceSettings>()
+        .init_resource::<slice::SliceView>()
+        .add_systems(Startup, (
+            settings::load_settings.before(setup),
+            setup,
+            save::load_on_startup.after(setup),
+            chat::setup_chat,
+            measure::setup_measure_labels,
+            targeting::setup_crosshair,
+            targeting::setup_placement_ghost,
+            hotbar::setup_hotbar,
+            catalog::setup_catalog,
+            locations::register_location_commands,
+            landmarks::register_landmark_commands,
+            locations::setup_warp_fade,
+            benchmark::register_benchmark_commands,
+            render_health::setup_render_health_banner,
+            debug_overlay::setup_debug_overlay,
+            exit::setup_exit_prompt,
+        ))
+        .add_systems(Update, (
+            cursor::toggle_grab.run_if(chat::is_closed).run_if(catalog::is_closed).run_if(exit::is_closed).before(chat::chat_input),
+            cursor::apply_grab.after(chat::chat_input),
+        ))
+        .add_systems(Update, (chat::chat_input.run_if(catalog::is_closed), chat::update_chat_ui).chain())
+        .add_systems(Update, (
+            // After anything else bound to the keys that close it, which
+            // still read as pressed this frame
+            catalog::catalog_input
+                .run_if(chat::is_closed)
+                .after(cursor::toggle_grab)
+                .after(hotbar::select_block)
+                .before(cursor::apply_grab),
+            catalog::update_catalog_ui,
+        ).chain())
+        .add_systems(Update, (
+            exit::request_exit,
+            exit::finish_exit_save,
+            exit::exit_prompt_input
+                .run_if(chat::is_closed)
+                .run_if(catalog::is_closed)
+                .after(cursor::toggle_grab)
+                .before(cursor::apply_grab),
+            exit::update_exit_prompt_ui,
+        ).chain())
+        .add_systems(Update, (locations::location_commands, locations::run_warp).chain().after(chat::chat_input))
+        .add_systems(Update, landmarks::landmark_commands.after(chat::chat_input))
+        .add_systems(Update, (
+            benchmark::benchmark_commands.after(chat::chat_input),
+            benchmark::run_rendering_benchmark,
+        ))
+        .add_systems(Update, (idle::apply_idle_settings, idle::drain_input_on_focus.before(player_movement)))
+        .add_systems(Update, player_movement.run_if(flythrough::is_idle).run_if(chat::is_closed).run_if(catalog::is_closed).run_if(cursor::is_grabbed))
+        .add_systems(Update, (
+            flythrough::edit_flythrough.run_if(chat::is_closed).run_if(catalog::is_closed),
+            flythrough::play_flythrough,
+        ))
+        .add_systems(Update, (
+            targeting::update_line_lock.before(targeting::update_target),
+            targeting::update_target,
+            targeting::draw_target_highlight.after(targeting::update_target),
+            targeting::update_placement_ghost.after(targeting::update_target),
+        ))
+        .add_systems(Update, (hotbar::select_block.run_if(chat::is_closed).run_if(catalog::is_closed), hotbar::update_hotbar).chain())
+        .add_systems(Update, place_block
+            .run_if(chat::is_closed)
+            .run_if(catalog::is_closed)
+            .run_if(cursor::is_grabbed)
+            .run_if(measure::is_inactive)
+            .after(targeting::update_target)
+            .after(cursor::toggle_grab))
+        .add_systems(Update, (save::save_world, save::load_world).run_if(chat::is_closed).run_if(catalog::is_closed))
+        .add_systems(Update, terrain::generate_terrain.before(voxel::rebuild_chunk_meshes))
+        .add_systems(Update, undo::undo_redo.run_if(chat::is_closed).run_if(catalog::is_closed).after(apply_block_events))
+        .add_systems(Update, (
+            apply_block_events.after(place_block),
+            voxel::rebuild_chunk_meshes
+                .after(apply_block_events)
+                .after(undo::undo_redo)
+                .after(save::load_world)
+                .after(slice::update_slice),
+        ))
+        .add_systems(Update, (
+            slice::update_slice.run_if(chat::is_closed).run_if(catalog::is_closed).before(targeting::update_target),
+            slice::draw_slice_plane.after(slice::update_slice),
+        ))
+        .add_systems(Update, (
+            measure::measure_input.run_if(chat::is_closed).run_if(catalog::is_closed).run_if(cursor::is_grabbed).after(targeting::update_target),
+            measure::draw_measurements,
+        ).chain())
+        .add_systems(Update, (
+            render_health::check_render_health,
+            render_health::rebuild_all_meshes.run_if(chat::is_closed).run_if(catalog::is_closed),
+            render_health::update_render_health_banner,
+        ).chain().before(voxel::rebuild_chunk_meshes))
+        .add_systems(Update, spike_capture::capture_spikes
+            .after(voxel::rebuild_chunk_meshes)
+            .after(terrain::generate_terrain))
+        .add_systems(Update, (
+            debug_overlay::toggle_debug_overlay.run_if(chat::is_closed).run_if(catalog::is_closed),
+            debug_overlay::update_debug_overlay
+                .after(voxel::rebuild_chunk_meshes)
+                .after(terrain::generate_terrain),
+        ).chain())
+        .add_systems(Update, (
+            graphics::graphics_input.run_if(chat::is_closed).run_if(catalog::is_closed),
+            graphics::auto_quality,
+            graphics::apply_graphics_settings,
+        ).chain())
+        .add_systems(Update, (
+            screenshot_mode::toggle_screenshot_mode.run_if(chat::is_closed).run_if(catalog::is_closed),
+            screenshot_mode::apply_screenshot_mode,
+        ).chain())
         .run();
 }
 
 fn setup(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
+    camera_settings: Res<CameraSettings>,
+    terrain_settings: Res<terrain::TerrainSettings>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
+    let ground = terrain::surface_height(&terrain_settings, 0, 0) as f32 + 1.0;
+
     // Camera
     commands.spawn((
         Name::new("Camera"),
         Camera3d::default(),
-        Transform::from_xyz(4.0, 4.0, 4.0)
-            .looking_at(Vec3::ZERO, Vec3::Z),
+        CollisionShape::default(),
+        Projection::Perspective(PerspectiveProjection {
+            fov: camera_settings.fov.to_radians(),
+            ..default()
+        }),
+        Transform::from_xyz(4.0, ground + 4.0, 4.0)
+            .looking_at(Vec3::new(0.0, ground, 0.0), Vec3::Z),
     ));
 
     // Light
     commands.spawn((
         Name::new("Light"),
         PointLight::default(),
-        Transform::from_xyz(3.0, 8.0, 5.0),
+        Transform::from_xyz(3.0, ground + 8.0, 5.0),
     ));
 
-    // Create shared mesh and material for instancing
-    let cube_mesh = meshes.add(Cuboid::default());
-    let cube_material = materials.add(Color::srgb(0.8, 0.7, 0.6));
-
-    // Spawn cubes using the same mesh and material handles
-    for x in 0..=CHUNK_SIZE {
-        for z in 0..=CHUNK_SIZE {
-            commands.spawn((
-                Name::new("Cube"),
-                Mesh3d(cube_mesh.clone()),
-                MeshMaterial3d(cube_material.clone()),
-                Transform::from_xyz(x as f32 * 2.0 + 0.5, 0.5, z as f32 * 2.0 + 0.5),
-            ));
-        }
-    }
-}
-
-
-fn grab_cursor(mut windows: Query<&mut Window>) {
-    let mut window = windows.single_mut();
-    window.cursor_options.grab_mode = CursorGrabMode::Locked;
-    window.cursor_options.visible = false;
+    commands.insert_resource(BlockAssets::new(&mut materials));
 }
 
 
 
 
 fn player_movement(
-    mut camera_query: Query<&mut Transform, With<Camera>>,
-    camera_settings: Res<CameraSettings>,
+    mut camera_query: Query<(&mut Transform, &mut CollisionShape), With<Camera>>,
+    mut camera_settings: ResMut<CameraSettings>,
+    mut physics: ResMut<PlayerPhysics>,
+    mut smoothing: ResMut<CameraSmoothing>,
+    world: Res<VoxelWorld>,
     keyboard: Res<ButtonInput<KeyCode>>,
     mut mouse_motion: EventReader<MouseMotion>,
     time: Res<Time>,
 ) {
-    let mut camera = camera_query.single_mut();
+    let (mut camera, mut shape) = camera_query.single_mut();
+
+    if keyboard.just_pressed(camera_settings.noclip_key) {
+        camera_settings.noclip = !camera_settings.noclip;
+    }
+    if keyboard.just_pressed(camera_settings.walking_key) {
+        camera_settings.walking = !camera_settings.walking;
+    }
+    if keyboard.just_pressed(camera_settings.speed_up_key) {
+        camera_settings.speed *= camera_settings.speed_step;
+    }
+    if keyboard.just_pressed(camera_settings.speed_down_key) {
+        camera_settings.speed /= camera_settings.speed_step;
+    }
+    camera_settings.speed = camera_settings.speed.clamp(
+        camera_settings.speed_range.start,
+        camera_settings.speed_range.end,
+    );
+    let walking = camera_settings.walking && !camera_settings.noclip;
+
+    // Crouch while descend is held in walking mode, and only stand back up
+    // once there's headroom
+    let crouching = walking && keyboard.pressed(camera_settings.descend_key);
+    let resized = match physics.standing {
+        None if crouching => Some(shape.crouched()),
+        Some(standing) if !crouching => Some(standing),
+        _ => None,
+    };
+    if let Some(resized) = resized {
+        if let Some(eye) = collision::resize(&world, camera.translation, *shape, resized) {
+            physics.standing = crouching.then_some(*shape);
+            camera.translation = eye;
+            *shape = resized;
+        }
+    }
     
-    // Handle mouse look
-    let (mut yaw, mut pitch, _) = camera.rotation.to_euler(EulerRot::YXZ);
+    // Handle mouse look. Anything else that turned the camera (warps,
+    // loading, flythroughs) restarts smoothing from where it now points.
+    if camera.rotation != smoothing.rotation {
+        let (yaw, pitch, _) = camera.rotation.to_euler(EulerRot::YXZ);
+        smoothing.look = Vec2::new(yaw, pitch);
+        smoothing.look_target = smoothing.look;
+    }
     
+    let pitch_sign = if camera_settings.invert_y { -1.0 } else { 1.0 };
     for event in mouse_motion.read() {
-        pitch -= event.delta.y * camera_settings.sensitivity;
-        yaw -= event.delta.x * camera_settings.sensitivity;
+        smoothing.look_target.y -= event.delta.y * camera_settings.sensitivity * pitch_sign;
+        smoothing.look_target.x -= event.delta.x * camera_settings.sensitivity;
     }
     
-    pitch = pitch.clamp(
+    smoothing.look_target.y = smoothing.look_target.y.clamp(
         camera_settings.pitch_range.start,
         camera_settings.pitch_range.end,
     );
-    
-    camera.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0);
+
+    let blend = smoothing_blend(camera_settings.smoothing, time.delta_secs());
+    smoothing.look = smoothing.look.lerp(smoothing.look_target, blend);
+    camera.rotation = Quat::from_euler(EulerRot::YXZ, smoothing.look.x, smoothing.look.y, 0.0);
+    smoothing.rotation = camera.rotation;
 
     // Handle keyboard input
     let mut velocity = Vec3::ZERO;
@@ -121,201 +450,526 @@ fn player_movement(
     let forward = Vec3::new(forward.x, 0.0, forward.z).normalize();
     let right = Vec3::new(right.x, 0.0, right.z).normalize();
 
-    if keyboard.pressed(KeyCode::KeyW) {
+    if keyboard.pressed(camera_settings.forward_key) {
         velocity += forward;
     }
-    if keyboard.pressed(KeyCode::KeyS) {
+    if keyboard.pressed(camera_settings.back_key) {
         velocity -= forward;
     }
-    if keyboard.pressed(KeyCode::KeyA) {
+    if keyboard.pressed(camera_settings.left_key) {
         velocity -= right;
     }
-    if keyboard.pressed(KeyCode::KeyD) {
+    if keyboard.pressed(camera_settings.right_key) {
         velocity += right;
     }
-    if keyboard.pressed(KeyCode::Space) {
+    if keyboard.pressed(camera_settings.jump_key) && !walking {
         velocity += Vec3::Y;
     }
-    if keyboard.pressed(KeyCode::ShiftLeft) {
+    if keyboard.pressed(camera_settings.descend_key) && !walking {
         velocity -= Vec3::Y;
     }
 
     if velocity != Vec3::ZERO {
         velocity = velocity.normalize();
     }
+    smoothing.velocity = smoothing.velocity.lerp(velocity, blend);
+    velocity = smoothing.velocity;
+
+    let delta = time.delta_secs();
+    let mut motion = velocity * camera_settings.speed * delta;
 
-    camera.translation += velocity * camera_settings.speed * time.delta_secs();
+    if walking {
+        // The block underfoot may have been removed since last frame, and
+        // jumping off thin air shouldn't work
+        if physics.grounded && !collision::has_support(&world, *shape, camera.translation) {
+            physics.grounded = false;
+        }
+        if physics.grounded && keyboard.pressed(camera_settings.jump_key) {
+            physics.vertical_speed = camera_settings.jump_speed;
+        }
+        physics.vertical_speed -= camera_settings.gravity * delta;
+        motion.y = physics.vertical_speed * delta;
+    } else {
+        physics.vertical_speed = 0.0;
+    }
+
+    if camera_settings.noclip {
+        camera.translation += motion;
+        physics.grounded = false;
+    } else {
+        let (position, blocked) =
+            collision::move_and_slide(&world, *shape, camera.translation, motion);
+        camera.translation = position;
+
+        // Landing and hitting a ceiling both stop vertical movement
+        physics.grounded = blocked.y && motion.y < 0.0;
+        if blocked.y {
+            physics.vertical_speed = 0.0;
+        }
+    }
 }
 
 fn place_block(
-    camera_query: Query<(&Camera, &GlobalTransform)>,
-    window_query: Query<&Window>,
-    block_query: Query<(Entity, &Transform)>,
+    camera_query: Query<&GlobalTransform, With<Camera>>,
+    target: Res<Target>,
+    mut line_lock: ResMut<LineLock>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    selected: Res<SelectedBlock>,
+    mut recent: ResMut<RecentBlocks>,
+    settings: Res<BuildSettings>,
+    mut world: ResMut<VoxelWorld>,
+    mut history: ResMut<EditHistory>,
     mouse_button: Res<ButtonInput<MouseButton>>,
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut remove_events: EventWriter<RemoveBlock>,
 ) {
-    // Only proceed if either left or right mouse button was just pressed
-    if !mouse_button.just_pressed(MouseButton::Left) && !mouse_button.just_pressed(MouseButton::Right) {
-        return;
-    }
-
-    let (camera, camera_transform) = camera_query.single();
-    let window = window_query.single();
-
-    if let Some(cursor_position) = window.cursor_position() {
-        if let Ok(ray) = camera.viewport_to_world(camera_transform, cursor_position) {
-            let max_distance = 10.0;
-            let ray_direction = ray.direction.normalize();
-            let ray_origin = ray.origin;
-
-            let mut hit_position = None;
-            let mut hit_normal = None;
-            let mut closest_distance = max_distance;
-            let mut hit_entity = None;
-
-            // Check for intersections with existing blocks
-            for (entity, transform) in block_query.iter() {
-                let block_pos = transform.translation;
-                let min = block_pos - Vec3::splat(0.5);
-                let max = block_pos + Vec3::splat(0.5);
-
-                if let Some((t, normal)) = ray_box_intersection(ray_origin, ray_direction, min, max) {
-                    if t < closest_distance {
-                        closest_distance = t;
-                        hit_position = Some(ray_origin + ray_direction * t);
-                        hit_normal = Some(normal);
-                        hit_entity = Some(entity);
-                    }
-                }
-            }
-
-            if let (Some(hit_pos), Some(normal), Some(entity)) = (hit_position, hit_normal, hit_entity) {
-                if mouse_button.just_pressed(MouseButton::Left) {
-                    // Place new block
-                    let hit_pos_rounded = Vec3::new(
-                        smart_round(hit_pos.x) - 0.5,
-                        smart_round(hit_pos.y) - 0.5,
-                        smart_round(hit_pos.z) - 0.5,
-                    );
-                    
-                    let grid_pos = hit_pos_rounded + normal;
-
-                    // Create shared mesh and material
-                    let cube_mesh = meshes.add(Cuboid::default());
-                    let cube_material = materials.add(Color::srgb(0.8, 0.7, 0.6));
-
-                    // Spawn a new cube at the grid position
-                    commands.spawn((
-                        Name::new("Cube"),
-                        Mesh3d(cube_mesh),
-                        MeshMaterial3d(cube_material),
-                        Transform::from_translation(grid_pos),
-                    ));
-                } else if mouse_button.just_pressed(MouseButton::Right) {
-                    // Remove the block that was hit
-                    commands.entity(entity).despawn();
-                }
+    if mouse_button.just_pressed(settings.place_button) {
+        if let Some(cell) = target.placement {
+            world.set_block(cell, selected.0);
+            recent.record(selected.0);
+            history.record(BlockEdit { cell, before: None, after: Some(selected.0) });
+
+            if keyboard.pressed(settings.axis_lock_key) && line_lock.0.is_none() {
+                let forward = *camera_query.single().forward();
+                line_lock.0 = Some(Line::facing(cell, forward, selected.0));
             }
         }
+    } else if mouse_button.just_pressed(settings.remove_button) {
+        // Remove the block that was hit
+        if let Some(hit) = target.hit {
+            remove_events.send(RemoveBlock(hit.cell));
+        }
     }
 }
 
-fn smart_round(x: f32) -> f32 {
-    if x >= 0.0 {
-        if x.fract() == 0.0 {
-            x
-        } else {
-            x.ceil()
-        }
+// Where a left click would put a block, or None if it would overwrite a
+// block or bury the camera. Shared by placement and the placement ghost.
+// The ray starts at the camera.
+fn placement_cell(
+    ray: Ray3d,
+    hit: VoxelHit,
+    assist: bool,
+    line: Option<Line>,
+    build_settings: &BuildSettings,
+    camera_settings: &CameraSettings,
+    shape: CollisionShape,
+    world: &VoxelWorld,
+) -> Option<IVec3> {
+    // Place new block in the cell the ray came from
+    let mut cell = hit.cell + hit.normal;
+    if assist {
+        cell = corner_assist_cell(ray, hit, build_settings, world).unwrap_or(cell);
+    }
+    if let Some(line) = line {
+        cell = line.snap(cell)?;
+    }
+
+    // With collision on, the whole player box has to stay clear
+    let eye = ray.origin;
+    let buries_player = if camera_settings.noclip {
+        cell == voxel::world_to_cell(eye)
     } else {
-        if x.fract() == 0.0 {
-            x
-        } else {
-            x.floor()
+        collision::player_overlaps(shape, eye, cell)
+    };
+    (world.get_block(cell).is_none() && !buries_player).then_some(cell)
+}
+
+// When the ray lands on a top face close to its outer edge and the cell
+// beyond that edge (and the one below it) is empty, returns that cell so
+// floors can be extended outward. Side-face hits never get here, so a
+// real side face in reach always wins.
+fn corner_assist_cell(
+    ray: Ray3d,
+    hit: VoxelHit,
+    settings: &BuildSettings,
+    world: &VoxelWorld,
+) -> Option<IVec3> {
+    let pitch = ray.direction.y.asin();
+    if hit.normal != IVec3::Y || !settings.corner_assist_pitch.contains(&pitch) {
+        return None;
+    }
+
+    // Pick the horizontal axis the hit is closest to the edge along
+    let hit_pos = ray.origin + ray.direction * hit.distance;
+    let local = hit_pos - voxel::cell_center(hit.cell);
+    let outward = if local.x.abs() >= local.z.abs() {
+        Vec3::new(local.x.signum(), 0.0, 0.0)
+    } else {
+        Vec3::new(0.0, 0.0, local.z.signum())
+    };
+    if local.dot(outward) < 0.5 - settings.corner_assist_edge || ray.direction.dot(outward) <= 0.0 {
+        return None;
+    }
+
+    let cell = hit.cell + outward.as_ivec3();
+    let occupied = |cell: IVec3| world.get_block(cell).is_some();
+    (!occupied(cell) && !occupied(cell - IVec3::Y)).then_some(cell)
+}
+
+// Gameplay removals go through here so two systems breaking the same block
+// in one frame remove it and record it in the edit history only once.
+// Undo and loading put back a known state instead and edit VoxelWorld
+// directly, since they mustn't be recorded as new edits.
+fn apply_block_events(
+    mut remove_events: EventReader<RemoveBlock>,
+    mut world: ResMut<VoxelWorld>,
+    mut history: ResMut<EditHistory>,
+) {
+    let mut removed = HashSet::new();
+
+    for &RemoveBlock(cell) in remove_events.read() {
+        if !removed.insert(cell) {
+            continue;
+        }
+
+        match world.remove_block(cell) {
+            Some(block) => history.record(BlockEdit { cell, before: Some(block), after: None }),
+            None => warn!("Ignoring removal at {cell}: block no longer exists"),
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use bevy::ecs::system::RunSystemOnce;
 
+    use super::*;
+    use crate::voxel::BlockType;
+
+    fn floor(corner: IVec3, size: i32) -> VoxelWorld {
+        let mut world = VoxelWorld::default();
+        for x in 0..size {
+            for z in 0..size {
+                world.set_block(corner + IVec3::new(x, 0, z), BlockType::Stone);
+            }
+        }
+        world
+    }
 
+    fn aim(
+        world: &VoxelWorld,
+        eye: Vec3,
+        direction: Vec3,
+        assist: bool,
+        line: Option<Line>,
+    ) -> Option<IVec3> {
+        aim_with(world, eye, direction, assist, line, &CameraSettings::default())
+    }
 
+    // Where the crosshair would place with the camera at `eye`
+    fn aim_with(
+        world: &VoxelWorld,
+        eye: Vec3,
+        direction: Vec3,
+        assist: bool,
+        line: Option<Line>,
+        camera_settings: &CameraSettings,
+    ) -> Option<IVec3> {
+        let ray = Ray3d::new(eye, Dir3::new(direction).unwrap());
+        let hit = world.raycast(ray.origin, *ray.direction, MAX_REACH)?;
+        let build_settings = BuildSettings::default();
+        let shape = CollisionShape::default();
+        placement_cell(ray, hit, assist, line, &build_settings, camera_settings, shape, world)
+    }
 
-fn ray_box_intersection(
-    ray_origin: Vec3,
-    ray_direction: Vec3,
-    box_min: Vec3,
-    box_max: Vec3,
-) -> Option<(f32, Vec3)> {
-    let mut tmin = (box_min.x - ray_origin.x) / ray_direction.x;
-    let mut tmax = (box_max.x - ray_origin.x) / ray_direction.x;
+    // Looking from `eye` at `target` with corner assist held
+    fn assist_at(world: &VoxelWorld, eye: Vec3, target: Vec3) -> Option<IVec3> {
+        aim(world, eye, target - eye, true, None)
+    }
 
-    if tmin > tmax {
-        std::mem::swap(&mut tmin, &mut tmax);
+    fn look(yaw: f32, pitch: f32) -> Vec3 {
+        let (yaw, pitch) = (yaw.to_radians(), pitch.to_radians());
+        Vec3::new(pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos())
     }
 
-    let mut tymin = (box_min.y - ray_origin.y) / ray_direction.y;
-    let mut tymax = (box_max.y - ray_origin.y) / ray_direction.y;
+    #[test]
+    fn places_against_the_top_face() {
+        let world = floor(IVec3::new(-2, 0, -2), 4);
+        let eye = Vec3::new(-1.5, 5.0, 0.5);
+        assert_eq!(aim(&world, eye, Vec3::NEG_Y, false, None), Some(IVec3::new(-2, 1, 0)));
+    }
 
-    if tymin > tymax {
-        std::mem::swap(&mut tymin, &mut tymax);
+    #[test]
+    fn places_against_bottom_and_side_faces() {
+        let mut world = VoxelWorld::default();
+        world.set_block(IVec3::new(0, 3, 0), BlockType::Stone);
+        world.set_block(IVec3::new(-3, -2, -5), BlockType::Stone);
+
+        let below = aim(&world, Vec3::splat(0.5), Vec3::Y, false, None);
+        assert_eq!(below, Some(IVec3::new(0, 2, 0)));
+        let west = aim(&world, Vec3::new(-6.5, -1.5, -4.5), Vec3::X, false, None);
+        assert_eq!(west, Some(IVec3::new(-4, -2, -5)));
+        let south = aim(&world, Vec3::new(-2.5, -1.5, -2.5), Vec3::NEG_Z, false, None);
+        assert_eq!(south, Some(IVec3::new(-3, -2, -4)));
     }
 
-    if tmin > tymax || tymin > tmax {
-        return None;
+    #[test]
+    fn places_from_an_eye_on_cell_boundaries() {
+        let world = floor(IVec3::new(-2, 0, -2), 4);
+        let from_origin = aim(&world, Vec3::new(0.0, 5.0, 0.0), Vec3::NEG_Y, false, None);
+        assert_eq!(from_origin, Some(IVec3::new(0, 1, 0)));
+        let from_negative = aim(&world, Vec3::new(-1.0, 5.0, -1.0), Vec3::NEG_Y, false, None);
+        assert_eq!(from_negative, Some(IVec3::new(-1, 1, -1)));
     }
 
-    if tymin > tmin {
-        tmin = tymin;
+    // Looking nearly straight down from anywhere within a cell, facing any
+    // way, places on top of the block under the crosshair
+    #[test]
+    fn near_vertical_rays_place_directly_under_the_crosshair() {
+        let world = floor(IVec3::splat(-8), 16);
+        let offsets = [0.0, 0.001, 0.25, 0.5, 0.75, 0.999];
+
+        for x in offsets {
+            for z in offsets {
+                for pitch in [-80.0, -85.0, -88.0, -89.5, -89.99] {
+                    for yaw in (0..360).step_by(30).map(|yaw| yaw as f32 + 7.0) {
+                        let eye = Vec3::new(x, -2.4, z);
+                        let direction = look(yaw, pitch);
+                        let crosshair = eye + direction * ((-7.0 - eye.y) / direction.y);
+                        let below = IVec3::new(crosshair.x.floor() as i32, -8, crosshair.z.floor() as i32);
+
+                        assert_eq!(
+                            aim(&world, eye, direction, false, None),
+                            Some(below + IVec3::Y),
+                            "eye {eye} yaw {yaw} pitch {pitch}"
+                        );
+                    }
+                }
+            }
+        }
     }
 
-    if tymax < tmax {
-        tmax = tymax;
+    #[test]
+    fn corner_assist_bridges_straight_out() {
+        let world = floor(IVec3::ZERO, 1);
+        let eye = Vec3::new(-1.5, 3.5, 0.5);
+        let edge = Vec3::new(0.9, 1.0, 0.5);
+
+        assert_eq!(assist_at(&world, eye, edge), Some(IVec3::X));
+        assert_eq!(aim(&world, eye, edge - eye, false, None), Some(IVec3::Y));
+        // Too far from the edge
+        assert_eq!(assist_at(&world, eye, Vec3::new(0.6, 1.0, 0.5)), Some(IVec3::Y));
+        // Looking back over the edge rather than out past it
+        assert_eq!(assist_at(&world, Vec3::new(3.5, 3.5, 0.5), edge), Some(IVec3::Y));
     }
 
-    let mut tzmin = (box_min.z - ray_origin.z) / ray_direction.z;
-    let mut tzmax = (box_max.z - ray_origin.z) / ray_direction.z;
+    #[test]
+    fn corner_assist_bridges_diagonally_along_the_nearer_edge() {
+        let world = floor(IVec3::ZERO, 1);
+        let eye = Vec3::new(-1.5, 3.5, -1.5);
 
-    if tzmin > tzmax {
-        std::mem::swap(&mut tzmin, &mut tzmax);
+        assert_eq!(assist_at(&world, eye, Vec3::new(0.9, 1.0, 0.85)), Some(IVec3::X));
+        assert_eq!(assist_at(&world, eye, Vec3::new(0.85, 1.0, 0.9)), Some(IVec3::Z));
     }
 
-    if tmin > tzmax || tzmin > tmax {
-        return None;
+    #[test]
+    fn corner_assist_bridges_across_chunk_borders() {
+        let world = floor(IVec3::new(15, 0, 3), 1);
+        let placement = assist_at(&world, Vec3::new(13.5, 3.5, 3.5), Vec3::new(15.9, 1.0, 3.5));
+        assert_eq!(placement, Some(IVec3::new(16, 0, 3)));
+
+        let world = floor(IVec3::ZERO, 1);
+        let placement = assist_at(&world, Vec3::new(2.5, 3.5, 0.5), Vec3::new(0.1, 1.0, 0.5));
+        assert_eq!(placement, Some(IVec3::NEG_X));
+        let placement = assist_at(&world, Vec3::new(0.5, 3.5, 2.5), Vec3::new(0.5, 1.0, 0.1));
+        assert_eq!(placement, Some(IVec3::NEG_Z));
     }
 
-    if tzmin > tmin {
-        tmin = tzmin;
+    #[test]
+    fn corner_assist_needs_empty_space_beyond_and_below() {
+        let eye = Vec3::new(-1.5, 3.5, 0.5);
+        let edge = Vec3::new(0.9, 1.0, 0.5);
+
+        // The floor carries on, so there's no edge to bridge over
+        let world = floor(IVec3::ZERO, 2);
+        assert_eq!(assist_at(&world, eye, edge), Some(IVec3::Y));
+
+        let mut world = floor(IVec3::ZERO, 1);
+        world.set_block(IVec3::new(1, -1, 0), BlockType::Stone);
+        assert_eq!(assist_at(&world, eye, edge), Some(IVec3::Y));
     }
 
-    // if tzmax < tmax {
-    //     tmax = tzmax; // Not needed, only for calc
-    // }
+    #[test]
+    fn corner_assist_leaves_side_face_hits_alone() {
+        let mut world = floor(IVec3::ZERO, 1);
+        world.set_block(IVec3::new(2, 1, 0), BlockType::Stone);
+        let eye = Vec3::new(0.5, 2.5, 0.5);
+        let side = Vec3::new(2.0, 1.5, 0.5);
+        let ray = Ray3d::new(eye, Dir3::new(side - eye).unwrap());
+
+        let hit = world.raycast(ray.origin, *ray.direction, MAX_REACH).unwrap();
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert_eq!(corner_assist_cell(ray, hit, &BuildSettings::default(), &world), None);
+        assert_eq!(assist_at(&world, eye, side), Some(IVec3::new(1, 1, 0)));
+    }
 
-    if tmin < 0.0 {
-        return None;
+    #[test]
+    fn corner_assist_only_works_within_its_pitch_range() {
+        let world = floor(IVec3::ZERO, 1);
+        // About 84 degrees down
+        let placement = assist_at(&world, Vec3::new(0.4, 6.0, 0.5), Vec3::new(0.9, 1.0, 0.5));
+        assert_eq!(placement, Some(IVec3::Y));
     }
 
-    // Calculate the hit point and normal
-    let hit_point = ray_origin + ray_direction * tmin;
-    let center = (box_min + box_max) * 0.5;
-    let half_size = (box_max - box_min) * 0.5;
-    
-    // Use a smaller epsilon value
-    const EPSILON: f32 = 0.0001;
-    
-    // Calculate the relative position from the center
-    let relative_pos = (hit_point - center).abs();
-    
-    // Determine which face was hit by comparing distances
-    let normal = if (relative_pos.x - half_size.x).abs() < EPSILON {
-        Vec3::new(if hit_point.x > center.x { 1.0 } else { -1.0 }, 0.0, 0.0)
-    } else if (relative_pos.y - half_size.y).abs() < EPSILON {
-        Vec3::new(0.0, if hit_point.y > center.y { 1.0 } else { -1.0 }, 0.0)
-    } else {
-        Vec3::new(0.0, 0.0, if hit_point.z > center.z { 1.0 } else { -1.0 })
-    };
+    #[test]
+    fn refuses_cells_that_would_bury_the_player() {
+        let world = floor(IVec3::new(-2, 0, -2), 4);
+        let eye = Vec3::new(0.5, 2.6, 0.5);
+        assert_eq!(aim(&world, eye, Vec3::NEG_Y, false, None), None);
+
+        // Without collision only the camera's own cell is kept clear
+        let noclip = CameraSettings {
+            noclip: true,
+            ..default()
+        };
+        let placement = aim_with(&world, eye, Vec3::NEG_Y, false, None, &noclip);
+        assert_eq!(placement, Some(IVec3::new(0, 1, 0)));
+    }
+
+    #[test]
+    fn refuses_occupied_cells() {
+        let mut world = floor(IVec3::new(-2, 0, -2), 4);
+        world.set_block(IVec3::new(0, 1, 0), BlockType::Glass);
+        let eye = Vec3::new(0.5, 5.0, 0.5);
+
+        // The glass is hit first, so placement goes on top of it instead
+        assert_eq!(aim(&world, eye, Vec3::NEG_Y, false, None), Some(IVec3::new(0, 2, 0)));
+        let line = Line::facing(IVec3::new(-2, 1, 0), Vec3::X, BlockType::Stone);
+        assert_eq!(aim(&world, eye, Vec3::NEG_Y, false, Some(line)), None);
+    }
+
+    #[test]
+    fn same_cell_removed_twice_in_one_frame_is_removed_once() {
+        let mut world = World::new();
+        world.init_resource::<Events<RemoveBlock>>();
+        world.init_resource::<EditHistory>();
+        let mut voxels = VoxelWorld::default();
+        voxels.set_block(IVec3::ZERO, BlockType::Stone);
+        voxels.set_block(IVec3::X, BlockType::Wood);
+        world.insert_resource(voxels);
+
+        // e.g. an explosion and a manual break hitting the same block
+        world.send_event(RemoveBlock(IVec3::ZERO));
+        world.send_event(RemoveBlock(IVec3::ZERO));
+        world.run_system_once(apply_block_events).unwrap();
+
+        let voxels = world.resource::<VoxelWorld>();
+        assert_eq!(voxels.get_block(IVec3::ZERO), None);
+        assert_eq!(voxels.get_block(IVec3::X), Some(BlockType::Wood));
+        assert_eq!(voxels.block_count(), 1);
+    }
+
+    // Walking on a single block, with the movement system run one frame
+    // at a time
+    fn walking_world() -> (World, Entity) {
+        let mut world = World::new();
+        world.insert_resource(CameraSettings { walking: true, ..default() });
+        world.init_resource::<PlayerPhysics>();
+        world.init_resource::<CameraSmoothing>();
+        world.insert_resource(floor(IVec3::ZERO, 1));
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<Events<MouseMotion>>();
+        world.init_resource::<Time>();
+        let camera = world
+            .spawn((
+                Camera3d::default(),
+                CollisionShape::default(),
+                Transform::from_xyz(0.5, 2.7, 0.5),
+            ))
+            .id();
+        (world, camera)
+    }
+
+    fn step(world: &mut World, keys: &[KeyCode]) {
+        let mut keyboard = world.resource_mut::<ButtonInput<KeyCode>>();
+        keyboard.reset_all();
+        for &key in keys {
+            keyboard.press(key);
+        }
+        world.resource_mut::<Time>().advance_by(Duration::from_secs_f32(1.0 / 60.0));
+        world.run_system_once(player_movement).unwrap();
+    }
+
+    fn eye(world: &World, camera: Entity) -> Vec3 {
+        world.get::<Transform>(camera).unwrap().translation
+    }
+
+    fn land(world: &mut World) {
+        for _ in 0..30 {
+            step(world, &[]);
+        }
+        assert!(world.resource::<PlayerPhysics>().grounded);
+    }
+
+    #[test]
+    fn jumps_off_solid_ground() {
+        let (mut world, camera) = walking_world();
+        land(&mut world);
+        let standing = eye(&world, camera);
+        let eye_height = CollisionShape::default().eye_height;
+        assert!((standing.y - (1.0 + eye_height)).abs() < 0.01, "{standing}");
+
+        step(&mut world, &[KeyCode::Space]);
+        assert!(eye(&world, camera).y > standing.y);
+        assert!(!world.resource::<PlayerPhysics>().grounded);
+    }
 
-    Some((tmin, normal))
-}
\ No newline at end of file
+    #[test]
+    fn falls_at_once_when_the_block_underfoot_is_removed() {
+        let (mut world, camera) = walking_world();
+        land(&mut world);
+        let standing = eye(&world, camera);
+        let shape = CollisionShape::default();
+        assert!(collision::has_support(world.resource::<VoxelWorld>(), shape, standing));
+
+        // e.g. broken by another player between two frames
+        world.resource_mut::<VoxelWorld>().remove_block(IVec3::ZERO);
+        assert!(!collision::has_support(world.resource::<VoxelWorld>(), shape, standing));
+
+        // Jump is held, but there's nothing left to jump off
+        step(&mut world, &[KeyCode::Space]);
+        let physics = world.resource::<PlayerPhysics>();
+        assert!(!physics.grounded);
+        assert!(physics.vertical_speed < 0.0);
+        let falling = eye(&world, camera);
+        assert!(falling.y < standing.y, "{falling} should be below {standing}");
+        assert_eq!(falling.xz(), standing.xz());
+    }
+
+    #[test]
+    fn lands_on_the_floor_below_without_clipping() {
+        let (mut world, camera) = walking_world();
+        land(&mut world);
+        world.insert_resource(floor(IVec3::new(-1, -6, -1), 3));
+
+        let shape = CollisionShape::default();
+        for _ in 0..120 {
+            step(&mut world, &[]);
+            let voxels = world.resource::<VoxelWorld>();
+            assert!(!collision::player_obstructed(voxels, shape, eye(&world, camera)));
+        }
+        let landed = eye(&world, camera);
+        assert!((landed.y - (-5.0 + shape.eye_height)).abs() < 0.01, "{landed}");
+        assert!(world.resource::<PlayerPhysics>().grounded);
+    }
+
+    #[test]
+    fn crouches_while_descend_is_held() {
+        let (mut world, camera) = walking_world();
+        land(&mut world);
+        let standing = eye(&world, camera);
+        let descend = world.resource::<CameraSettings>().descend_key;
+        let shape = |world: &World| *world.get::<CollisionShape>(camera).unwrap();
+
+        step(&mut world, &[descend]);
+        let crouched = eye(&world, camera);
+        assert!((standing.y - crouched.y - 0.3).abs() < 0.01, "{crouched}");
+        assert_eq!(shape(&world), CollisionShape::default().crouched());
+        assert!(world.resource::<PlayerPhysics>().grounded);
+
+        step(&mut world, &[]);
+        assert!((eye(&world, camera).y - standing.y).abs() < 0.01);
+        assert_eq!(shape(&world), CollisionShape::default());
+        assert_eq!(world.resource::<PlayerPhysics>().standing, None);
+    }
+}
@@ -0,0 +1,270 @@
+use bevy::prelude::*;
+
+use crate::{screenshot_mode::ScreenshotMode, targeting::Target, voxel::cell_center};
+
+
+#[derive(Debug, Resource)]
+pub struct MeasureSettings {
+    pub toggle_key: KeyCode,
+    pub clear_key: KeyCode,
+    // Marks the anchor, then locks the measurement
+    pub mark_button: MouseButton,
+    // Drops the anchor without locking anything
+    pub cancel_button: MouseButton,
+    pub max_locked: usize,
+    pub color: Color,
+    pub locked_color: Color,
+}
+
+impl Default for MeasureSettings {
+    fn default() -> Self {
+        Self {
+            toggle_key: KeyCode::KeyM,
+            clear_key: KeyCode::KeyN,
+            mark_button: MouseButton::Left,
+            cancel_button: MouseButton::Right,
+            max_locked: 3,
+            color: Color::srgb(1.0, 0.9, 0.2),
+            locked_color: Color::srgb(0.3, 0.8, 1.0),
+        }
+    }
+}
+
+// Measurements snap to the centers of the targeted cells so deltas are whole blocks
+#[derive(Debug, Default, Resource)]
+pub struct MeasureTool {
+    pub active: bool,
+    pub anchor: Option<Vec3>,
+    pub target: Option<Vec3>,
+    pub locked: Vec<(Vec3, Vec3)>,
+}
+
+// Index 0 labels the live measurement, the rest label locked ones
+#[derive(Component)]
+pub struct MeasureLabel(usize);
+
+// Run condition so clicks measure instead of editing blocks while the tool is out
+pub fn is_inactive(tool: Res<MeasureTool>) -> bool {
+    !tool.active
+}
+
+pub fn setup_measure_labels(mut commands: Commands, settings: Res<MeasureSettings>) {
+    commands
+        .spawn((
+            Name::new("Measure Labels"),
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            for index in 0..=settings.max_locked {
+                parent.spawn((
+                    MeasureLabel(index),
+                    Text::default(),
+                    TextFont {
+                        font_size: 18.0,
+                        ..default()
+                    },
+                    TextColor(Color::WHITE),
+                    BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+                    Node {
+                        position_type: PositionType::Absolute,
+                        ..default()
+                    },
+                    Visibility::Hidden,
+                ));
+            }
+        });
+}
+
+pub fn measure_input(
+    target: Res<Target>,
+    settings: Res<MeasureSettings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    mut tool: ResMut<MeasureTool>,
+) {
+    if keyboard.just_pressed(settings.toggle_key) {
+        tool.active = !tool.active;
+        tool.anchor = None;
+    }
+    if keyboard.just_pressed(settings.clear_key) {
+        tool.locked.clear();
+    }
+
+    if !tool.active {
+        tool.target = None;
+        return;
+    }
+
+    tool.target = target.hit.map(|hit| cell_center(hit.cell));
+
+    if mouse_button.just_pressed(settings.cancel_button) {
+        tool.anchor = None;
+    }
+
+    let Some(target) = tool.target else {
+        return;
+    };
+
+    if mouse_button.just_pressed(settings.mark_button) {
+        match tool.anchor.take() {
+            None => tool.anchor = Some(target),
+            Some(anchor) => {
+                tool.locked.push((anchor, target));
+                if tool.locked.len() > settings.max_locked {
+                    tool.locked.remove(0);
+                }
+            }
+        }
+    }
+}
+
+pub fn draw_measurements(
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mut label_query: Query<(&MeasureLabel, &mut Text, &mut Node, &mut Visibility)>,
+    settings: Res<MeasureSettings>,
+    tool: Res<MeasureTool>,
+    screenshot_mode: Res<ScreenshotMode>,
+    mut gizmos: Gizmos,
+) {
+    // The labels sit under a UI root, which screenshot mode already hides
+    if screenshot_mode.active {
+        return;
+    }
+
+    let (camera, camera_transform) = camera_query.single();
+
+    let live = tool.anchor.zip(tool.target);
+    let measurements = std::iter::once(live.map(|m| (m, settings.color)))
+        .chain(tool.locked.iter().map(|m| Some((*m, settings.locked_color))))
+        .collect::<Vec<_>>();
+
+    if let Some(anchor) = tool.anchor {
+        draw_cell(&mut gizmos, anchor, settings.color);
+    }
+    if let Some(target) = tool.target {
+        draw_cell(&mut gizmos, target, settings.color);
+    }
+
+    for (label, mut text, mut node, mut visibility) in &mut label_query {
+        let Some(Some(((from, to), color))) = measurements.get(label.0).copied() else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+
+        gizmos.line(from, to, color);
+        draw_cell(&mut gizmos, from, color);
+        draw_cell(&mut gizmos, to, color);
+
+        // Screen-space label at the midpoint stays readable at any distance
+        let Ok(screen_pos) = camera.world_to_viewport(camera_transform, (from + to) * 0.5) else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+
+        text.0 = label_text(from, to);
+        node.left = Val::Px(screen_pos.x);
+        node.top = Val::Px(screen_pos.y);
+        *visibility = Visibility::Inherited;
+    }
+}
+
+fn label_text(from: Vec3, to: Vec3) -> String {
+    // Adding zero turns -0.0 into 0.0 so the label never shows "-0"
+    let delta = (to - from).round() + Vec3::ZERO;
+    format!("dx {}  dy {}  dz {}  ({:.2})", delta.x, delta.y, delta.z, delta.length())
+}
+
+fn draw_cell(gizmos: &mut Gizmos, center: Vec3, color: Color) {
+    gizmos.cuboid(
+        Transform::from_translation(center).with_scale(Vec3::splat(1.02)),
+        color,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::voxel::VoxelHit;
+
+    fn measure_world() -> World {
+        let mut world = World::new();
+        world.init_resource::<Target>();
+        world.init_resource::<MeasureSettings>();
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<ButtonInput<MouseButton>>();
+        world.insert_resource(MeasureTool { active: true, ..default() });
+        world
+    }
+
+    fn click(world: &mut World, cell: IVec3, button: MouseButton) {
+        world.resource_mut::<Target>().hit = Some(VoxelHit {
+            cell,
+            normal: IVec3::Y,
+            distance: 1.0,
+        });
+        let mut mouse_button = world.resource_mut::<ButtonInput<MouseButton>>();
+        mouse_button.reset_all();
+        mouse_button.press(button);
+        world.run_system_once(measure_input).unwrap();
+    }
+
+    fn measure(world: &mut World, from: IVec3, to: IVec3) {
+        click(world, from, MouseButton::Left);
+        click(world, to, MouseButton::Left);
+    }
+
+    #[test]
+    fn keeps_the_newest_locked_measurements() {
+        let mut world = measure_world();
+        for x in 1..=5 {
+            measure(&mut world, IVec3::ZERO, IVec3::new(x, 0, 0));
+        }
+
+        let tool = world.resource::<MeasureTool>();
+        assert_eq!(tool.locked.len(), 3);
+        let ends = tool.locked.iter().map(|(_, to)| to.x).collect::<Vec<_>>();
+        assert_eq!(ends, [3.5, 4.5, 5.5]);
+        assert_eq!(tool.anchor, None);
+    }
+
+    #[test]
+    fn cancel_drops_the_anchor() {
+        let mut world = measure_world();
+        click(&mut world, IVec3::ZERO, MouseButton::Left);
+        click(&mut world, IVec3::X, MouseButton::Right);
+
+        let tool = world.resource::<MeasureTool>();
+        assert_eq!(tool.anchor, None);
+        assert!(tool.locked.is_empty());
+    }
+
+    #[test]
+    fn buttons_come_from_the_settings() {
+        let mut world = measure_world();
+        let mut settings = world.resource_mut::<MeasureSettings>();
+        settings.mark_button = MouseButton::Middle;
+        settings.cancel_button = MouseButton::Back;
+
+        click(&mut world, IVec3::ZERO, MouseButton::Left);
+        assert_eq!(world.resource::<MeasureTool>().anchor, None);
+        click(&mut world, IVec3::ZERO, MouseButton::Middle);
+        assert_eq!(world.resource::<MeasureTool>().anchor, Some(Vec3::splat(0.5)));
+        click(&mut world, IVec3::ZERO, MouseButton::Back);
+        assert_eq!(world.resource::<MeasureTool>().anchor, None);
+    }
+
+    #[test]
+    fn labels_show_whole_block_deltas_and_the_distance() {
+        let label = |to: IVec3| label_text(cell_center(IVec3::new(2, 0, -1)), cell_center(to));
+        assert_eq!(label(IVec3::new(5, 4, -1)), "dx 3  dy 4  dz 0  (5.00)");
+        assert_eq!(label(IVec3::new(0, 0, -2)), "dx -2  dy 0  dz -1  (2.24)");
+        assert_eq!(label(IVec3::new(2, 0, -1)), "dx 0  dy 0  dz 0  (0.00)");
+    }
+}
@@ -0,0 +1,230 @@
+use bevy::prelude::*;
+
+use crate::voxel::{BlockAssets, BlockType, ChunkEntities, VoxelWorld};
+
+
+const CHECK_INTERVAL: f32 = 2.0;
+// Beyond this many broken chunk meshes something is badly wrong, so the
+// player is told instead of it only going to the log
+const BANNER_THRESHOLD: usize = 8;
+
+#[derive(Debug, Resource)]
+pub struct RenderHealthSettings {
+    pub rebuild_key: KeyCode,
+}
+
+impl Default for RenderHealthSettings {
+    fn default() -> Self {
+        Self {
+            rebuild_key: KeyCode::F10,
+        }
+    }
+}
+
+// Chunk meshes whose mesh or material asset was found missing at the
+// last check
+#[derive(Debug, Default, Resource)]
+pub struct RenderHealth {
+    since_check: f32,
+    unhealthy: usize,
+}
+
+#[derive(Component)]
+pub struct RenderHealthBanner;
+
+// Screenshot mode owns the visibility of UI roots, so the banner toggles
+// its text node instead of the root
+pub fn setup_render_health_banner(mut commands: Commands) {
+    commands
+        .spawn((
+            Name::new("Render Health Banner"),
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                top: Val::Px(12.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+        ))
+        .with_child((
+            RenderHealthBanner,
+            Text::default(),
+            TextColor(Color::srgb(1.0, 0.3, 0.3)),
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+            Visibility::Hidden,
+        ));
+}
+
+// Lost meshes are simply rebuilt. Lost materials can't be told apart from
+// the right colour, so those chunks turn magenta until a full rebuild.
+pub fn check_render_health(
+    mut health: ResMut<RenderHealth>,
+    mut world: ResMut<VoxelWorld>,
+    chunk_entities: Res<ChunkEntities>,
+    block_assets: Res<BlockAssets>,
+    meshes: Res<Assets<Mesh>>,
+    materials: Res<Assets<StandardMaterial>>,
+    mut material_query: Query<&mut MeshMaterial3d<StandardMaterial>>,
+    time: Res<Time>,
+) {
+    health.since_check += time.delta_secs();
+    if health.since_check < CHECK_INTERVAL {
+        return;
+    }
+    health.since_check = 0.0;
+
+    let mut unhealthy = 0;
+    for (chunk, block, entity, mesh) in chunk_entities.iter() {
+        let mut broken = false;
+
+        if !meshes.contains(mesh) {
+            world.mark_chunk_dirty(chunk);
+            broken = true;
+        }
+
+        if !materials.contains(&block_assets.materials[&block]) {
+            if let Ok(mut material) = material_query.get_mut(entity) {
+                material.0 = block_assets.error_material.clone();
+            }
+            broken = true;
+        }
+
+        if broken {
+            unhealthy += 1;
+        }
+    }
+
+    if unhealthy > 0 {
+        warn!("{unhealthy} chunk meshes lost their assets");
+    }
+    health.unhealthy = unhealthy;
+}
+
+pub fn update_render_health_banner(
+    health: Res<RenderHealth>,
+    settings: Res<RenderHealthSettings>,
+    mut banner_query: Query<(&mut Text, &mut Visibility), With<RenderHealthBanner>>,
+) {
+    let (mut text, mut visibility) = banner_query.single_mut();
+    if health.unhealthy <= BANNER_THRESHOLD {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    }
+
+    visibility.set_if_neq(Visibility::Inherited);
+    text.0 = format!(
+        "{} chunks failed to render. Press {:?} to rebuild all meshes.",
+        health.unhealthy, settings.rebuild_key
+    );
+}
+
+// Puts back any missing block materials and remeshes every chunk
+pub fn rebuild_all_meshes(
+    settings: Res<RenderHealthSettings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut health: ResMut<RenderHealth>,
+    mut world: ResMut<VoxelWorld>,
+    chunk_entities: Res<ChunkEntities>,
+    block_assets: Res<BlockAssets>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut material_query: Query<&mut MeshMaterial3d<StandardMaterial>>,
+) {
+    if !keyboard.just_pressed(settings.rebuild_key) {
+        return;
+    }
+
+    for block in BlockType::ALL {
+        let handle = &block_assets.materials[&block];
+        if !materials.contains(handle) {
+            materials.insert(handle, block.material());
+        }
+    }
+
+    for (_, block, entity, _) in chunk_entities.iter() {
+        if let Ok(mut material) = material_query.get_mut(entity) {
+            material.0 = block_assets.materials[&block].clone();
+        }
+    }
+
+    world.mark_layers_dirty(i32::MIN..=i32::MAX);
+    health.unhealthy = 0;
+    info!("Rebuilding all chunk meshes");
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::{budget::FrameBudget, slice::SliceView, voxel};
+
+    fn test_world() -> World {
+        let mut world = World::new();
+        let mut materials = Assets::<StandardMaterial>::default();
+        world.insert_resource(BlockAssets::new(&mut materials));
+        world.insert_resource(materials);
+        world.init_resource::<Assets<Mesh>>();
+        world.init_resource::<VoxelWorld>();
+        world.init_resource::<ChunkEntities>();
+        world.init_resource::<RenderHealth>();
+        world.init_resource::<SliceView>();
+        world.init_resource::<FrameBudget>();
+        world.init_resource::<Time>();
+        world.spawn((Camera::default(), GlobalTransform::default()));
+        world
+    }
+
+    fn check_now(world: &mut World) {
+        world.resource_mut::<RenderHealth>().since_check = CHECK_INTERVAL;
+        world.run_system_once(check_render_health).unwrap();
+    }
+
+    #[test]
+    fn removed_mesh_asset_is_detected_and_rebuilt() {
+        let mut world = test_world();
+        world
+            .resource_mut::<VoxelWorld>()
+            .set_block(IVec3::new(5, 5, 5), BlockType::Stone);
+        world.run_system_once(voxel::rebuild_chunk_meshes).unwrap();
+        assert_eq!(world.resource::<VoxelWorld>().dirty_chunk_count(), 0);
+
+        let mesh = world
+            .resource::<ChunkEntities>()
+            .iter()
+            .map(|(_, _, _, mesh)| mesh.clone())
+            .next()
+            .unwrap();
+        world.resource_mut::<Assets<Mesh>>().remove(&mesh);
+
+        check_now(&mut world);
+        assert_eq!(world.resource::<RenderHealth>().unhealthy, 1);
+        assert_eq!(world.resource::<VoxelWorld>().dirty_chunk_count(), 1);
+
+        world.run_system_once(voxel::rebuild_chunk_meshes).unwrap();
+        assert!(world.resource::<Assets<Mesh>>().contains(&mesh));
+
+        check_now(&mut world);
+        assert_eq!(world.resource::<RenderHealth>().unhealthy, 0);
+    }
+
+    #[test]
+    fn removed_material_shows_error_material() {
+        let mut world = test_world();
+        world
+            .resource_mut::<VoxelWorld>()
+            .set_block(IVec3::new(5, 5, 5), BlockType::Wood);
+        world.run_system_once(voxel::rebuild_chunk_meshes).unwrap();
+
+        let material = world.resource::<BlockAssets>().materials[&BlockType::Wood].clone();
+        world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .remove(&material);
+
+        check_now(&mut world);
+        assert_eq!(world.resource::<RenderHealth>().unhealthy, 1);
+
+        let error_material = world.resource::<BlockAssets>().error_material.clone();
+        let mut query = world.query::<&MeshMaterial3d<StandardMaterial>>();
+        assert!(query.iter(&world).all(|material| material.0 == error_material));
+    }
+}
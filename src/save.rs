@@ -0,0 +1,652 @@
+use std::{collections::BTreeMap, env, fs, io, path::{Path, PathBuf}};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chat::ChatState,
+    landmarks::WorldMap,
+    locations::{Location, NamedLocations},
+    terrain::{TerrainQueue, TerrainSettings},
+    undo::EditHistory,
+    voxel::{BlockType, VoxelWorld},
+};
+
+
+// Bump when the layout of `SavedWorld` changes
+const SAVE_VERSION: u32 = 2;
+// Oldest version still loaded. Version 1 had no named locations.
+const MIN_SAVE_VERSION: u32 = 1;
+// Used for palette entries this build doesn't know, e.g. from a newer version
+const FALLBACK_BLOCK: BlockType = BlockType::Stone;
+
+#[derive(Debug, Resource)]
+pub struct SaveSettings {
+    pub save_key: KeyCode,
+    pub load_key: KeyCode,
+    pub path: PathBuf,
+    pub load_on_startup: bool,
+}
+
+impl Default for SaveSettings {
+    fn default() -> Self {
+        Self {
+            save_key: KeyCode::F5,
+            load_key: KeyCode::F9,
+            path: PathBuf::from("world.ron"),
+            load_on_startup: false,
+        }
+    }
+}
+
+impl SaveSettings {
+    // `--load <path>` loads that world at startup and saves back to it
+    pub fn from_args() -> Self {
+        let mut settings = Self::default();
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg == "--load" {
+                match args.next() {
+                    Some(path) => {
+                        settings.path = PathBuf::from(path);
+                        settings.load_on_startup = true;
+                    }
+                    // Logging isn't set up yet this early
+                    None => eprintln!("--load needs a path"),
+                }
+            }
+        }
+        settings
+    }
+}
+
+// Read on its own first so any future format can be told apart before
+// the rest of the file is parsed
+#[derive(Debug, Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+// Block types are stored by name through a palette so renaming or
+// reordering the enum doesn't break old saves
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedWorld {
+    pub version: u32,
+    pub camera: Option<SavedCamera>,
+    pub palette: Vec<String>,
+    pub blocks: Vec<(IVec3, usize)>,
+    #[serde(default)]
+    pub locations: Vec<SavedLocation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedLocation {
+    pub name: String,
+    pub position: Vec3,
+    pub yaw: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SavedCamera {
+    pub translation: Vec3,
+    pub rotation: Quat,
+}
+
+// What the save file last matched, from a save or a load, so quitting can
+// tell whether anything would be lost. Generated terrain counts as a
+// change too, since it's only on disk once saved.
+#[derive(Debug, Default, Resource)]
+pub struct UnsavedChanges {
+    saved_revision: u64,
+    saved_locations: BTreeMap<String, Location>,
+}
+
+impl UnsavedChanges {
+    pub fn is_dirty(&self, world: &VoxelWorld, locations: &NamedLocations) -> bool {
+        world.revision() != self.saved_revision || locations.locations != self.saved_locations
+    }
+
+    pub fn mark_saved(&mut self, world: &VoxelWorld, locations: &NamedLocations) {
+        self.saved_revision = world.revision();
+        self.saved_locations = locations.locations.clone();
+    }
+}
+
+pub fn save_world(
+    camera_query: Query<&Transform, With<Camera>>,
+    settings: Res<SaveSettings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    world: Res<VoxelWorld>,
+    locations: Res<NamedLocations>,
+    mut unsaved: ResMut<UnsavedChanges>,
+) {
+    if !keyboard.just_pressed(settings.save_key) {
+        return;
+    }
+
+    if save_to_file(&settings.path, &world, &locations, camera_query.single()) {
+        unsaved.mark_saved(&world, &locations);
+    }
+}
+
+// Any failure is logged. Returns whether the file was written.
+pub fn save_to_file(
+    path: &Path,
+    world: &VoxelWorld,
+    locations: &NamedLocations,
+    camera: &Transform,
+) -> bool {
+    let saved = saved_world(world, locations, camera);
+
+    let contents = match ron::ser::to_string_pretty(&saved, ron::ser::PrettyConfig::default()) {
+        Ok(contents) => contents,
+        Err(err) => {
+            error!("Failed to serialize world: {err}");
+            return false;
+        }
+    };
+
+    match write_atomic(path, &contents) {
+        Ok(()) => {
+            info!("Saved {} blocks to {}", saved.blocks.len(), path.display());
+            true
+        }
+        Err(err) => {
+            error!("Failed to save world to {}: {err}", path.display());
+            false
+        }
+    }
+}
+
+fn saved_world(world: &VoxelWorld, locations: &NamedLocations, camera: &Transform) -> SavedWorld {
+    let palette = BlockType::ALL.iter().map(|block| block.name().to_string()).collect();
+
+    // Sorted so saving an unchanged world produces an identical file
+    let mut blocks = world
+        .blocks()
+        .map(|(cell, block)| (cell, BlockType::ALL.iter().position(|b| *b == block).unwrap_or(0)))
+        .collect::<Vec<_>>();
+    blocks.sort_by_key(|(cell, _)| (cell.x, cell.y, cell.z));
+
+    SavedWorld {
+        version: SAVE_VERSION,
+        camera: Some(SavedCamera {
+            translation: camera.translation,
+            rotation: camera.rotation,
+        }),
+        palette,
+        blocks,
+        locations: locations
+            .locations
+            .iter()
+            .map(|(name, location)| SavedLocation {
+                name: name.clone(),
+                position: location.position,
+                yaw: location.yaw,
+            })
+            .collect(),
+    }
+}
+
+pub fn load_world(
+    mut camera_query: Query<&mut Transform, With<Camera>>,
+    settings: Res<SaveSettings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut world: ResMut<VoxelWorld>,
+    mut locations: ResMut<NamedLocations>,
+    mut history: ResMut<EditHistory>,
+    mut terrain: ResMut<TerrainQueue>,
+    mut map: ResMut<WorldMap>,
+    mut chat: ResMut<ChatState>,
+    mut unsaved: ResMut<UnsavedChanges>,
+    time: Res<Time>,
+) {
+    if !keyboard.just_pressed(settings.load_key) {
+        return;
+    }
+
+    let mut camera = camera_query.single_mut();
+    if let Some(repairs) = load_from_file(&settings.path, &mut world, &mut locations, &mut camera) {
+        // Edits made to the previous world don't apply to the loaded one,
+        // and terrain still being generated would land on top of it.
+        // Landmarks aren't saved, so the map starts over.
+        history.clear();
+        *terrain = TerrainQueue::default();
+        map.landmarks.clear();
+        unsaved.mark_saved(&world, &locations);
+        report_repairs(&settings.path, &repairs, &mut chat, time.elapsed_secs());
+    }
+}
+
+// Without a world to load, or if it fails to load, terrain is generated
+// instead so the player doesn't start in an empty void
+pub fn load_on_startup(
+    mut camera_query: Query<&mut Transform, With<Camera>>,
+    settings: Res<SaveSettings>,
+    terrain_settings: Res<TerrainSettings>,
+    mut world: ResMut<VoxelWorld>,
+    mut locations: ResMut<NamedLocations>,
+    mut terrain: ResMut<TerrainQueue>,
+    mut chat: ResMut<ChatState>,
+    mut unsaved: ResMut<UnsavedChanges>,
+) {
+    if settings.load_on_startup {
+        let mut camera = camera_query.single_mut();
+        if let Some(repairs) = load_from_file(&settings.path, &mut world, &mut locations, &mut camera) {
+            unsaved.mark_saved(&world, &locations);
+            report_repairs(&settings.path, &repairs, &mut chat, 0.0);
+            return;
+        }
+    }
+
+    info!("Generating terrain with seed {}", terrain_settings.seed);
+    *terrain = TerrainQueue::around_origin(terrain_settings.radius);
+}
+
+fn report_repairs(path: &Path, repairs: &[String], chat: &mut ChatState, now: f32) {
+    if repairs.is_empty() {
+        return;
+    }
+
+    chat.push_system(format!("{} needed repairs while loading:", path.display()), now);
+    for repair in repairs {
+        chat.push_system(format!("- {repair}"), now);
+    }
+}
+
+// Any failure is logged and leaves the current world as it is. On success
+// returns what had to be repaired to make the save usable, if anything.
+fn load_from_file(
+    path: &Path,
+    world: &mut VoxelWorld,
+    locations: &mut NamedLocations,
+    camera: &mut Transform,
+) -> Option<Vec<String>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            error!("Failed to load world from {}: {err}", path.display());
+            return None;
+        }
+    };
+
+    match ron::from_str::<SaveHeader>(&contents) {
+        Ok(header) if (MIN_SAVE_VERSION..=SAVE_VERSION).contains(&header.version) => {}
+        Ok(header) => {
+            error!("{} has unsupported save version {}", path.display(), header.version);
+            return None;
+        }
+        Err(err) => {
+            error!("Malformed world file {}: {err}", path.display());
+            return None;
+        }
+    }
+
+    let saved = match ron::from_str::<SavedWorld>(&contents) {
+        Ok(saved) => saved,
+        Err(err) => {
+            error!("Malformed world file {}: {err}", path.display());
+            return None;
+        }
+    };
+
+    let mut repairs = Vec::new();
+
+    let mut palette = Vec::new();
+    for name in &saved.palette {
+        palette.push(BlockType::from_name(name).unwrap_or_else(|| {
+            repairs.push(format!("unknown block type '{name}' replaced with {FALLBACK_BLOCK:?}"));
+            FALLBACK_BLOCK
+        }));
+    }
+
+    // Hand-edited or half-written saves can repeat a cell; the last entry wins
+    let mut bad_indices = 0;
+    let mut duplicates = 0;
+    world.clear();
+    for (cell, index) in &saved.blocks {
+        let block = palette.get(*index).copied().unwrap_or_else(|| {
+            bad_indices += 1;
+            FALLBACK_BLOCK
+        });
+        if world.get_block(*cell).is_some() {
+            duplicates += 1;
+        }
+        world.set_block(*cell, block);
+    }
+    if bad_indices > 0 {
+        repairs.push(format!("{bad_indices} blocks with no palette entry replaced with {FALLBACK_BLOCK:?}"));
+    }
+    if duplicates > 0 {
+        repairs.push(format!("{duplicates} duplicate blocks dropped"));
+    }
+
+    // Marks belong to the world they were made in
+    let mut bad_locations = 0;
+    locations.locations.clear();
+    for location in saved.locations {
+        if !location.position.is_finite() || !location.yaw.is_finite() {
+            bad_locations += 1;
+            continue;
+        }
+        locations.locations.insert(
+            location.name,
+            Location {
+                position: location.position,
+                yaw: location.yaw,
+            },
+        );
+    }
+    if bad_locations > 0 {
+        repairs.push(format!("{bad_locations} named locations with invalid positions dropped"));
+    }
+
+    match saved.camera {
+        Some(saved_camera)
+            if saved_camera.translation.is_finite() && saved_camera.rotation.is_finite() =>
+        {
+            camera.translation = saved_camera.translation;
+            camera.rotation = saved_camera.rotation.normalize();
+        }
+        Some(_) => repairs.push("invalid camera position ignored".to_string()),
+        None => {}
+    }
+
+    for repair in &repairs {
+        warn!("Repaired {}: {repair}", path.display());
+    }
+    info!("Loaded {} blocks from {}", world.block_count(), path.display());
+    Some(repairs)
+}
+
+// Write to a sibling temp file and rename over the target, so a crash
+// mid-write can't leave a truncated save behind
+fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    let temp_path = path.with_extension("ron.tmp");
+    fs::write(&temp_path, contents)?;
+    fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    // Unique per test so tests running in parallel don't share a file
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("castle_wars-{}-{name}.ron", std::process::id()))
+    }
+
+    fn write_saved(path: &Path, saved: &SavedWorld) {
+        let config = ron::ser::PrettyConfig::default();
+        let contents = ron::ser::to_string_pretty(saved, config).unwrap();
+        write_atomic(path, &contents).unwrap();
+    }
+
+    #[test]
+    fn world_survives_a_save_and_load() {
+        let mut world = VoxelWorld::default();
+        world.set_block(IVec3::new(-3, 0, 7), BlockType::Stone);
+        world.set_block(IVec3::new(0, 1, 0), BlockType::Glass);
+        world.set_block(IVec3::new(2, -4, -1), BlockType::Wood);
+        let mut locations = NamedLocations::default();
+        let gate = Location {
+            position: Vec3::new(4.0, 2.5, -6.0),
+            yaw: 1.25,
+        };
+        locations.locations.insert("gate".to_string(), gate);
+        let camera = Transform::from_xyz(1.5, 3.0, -2.0).looking_at(Vec3::ZERO, Vec3::Y);
+
+        let path = temp_path("round-trip");
+        write_saved(&path, &saved_world(&world, &locations, &camera));
+
+        let mut loaded_world = VoxelWorld::default();
+        loaded_world.set_block(IVec3::new(9, 9, 9), BlockType::Stone);
+        let mut loaded_locations = NamedLocations::default();
+        let mut loaded_camera = Transform::default();
+        let repairs =
+            load_from_file(&path, &mut loaded_world, &mut loaded_locations, &mut loaded_camera);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(repairs, Some(Vec::new()));
+        let mut expected = world.blocks().collect::<Vec<_>>();
+        let mut actual = loaded_world.blocks().collect::<Vec<_>>();
+        expected.sort_by_key(|(cell, _)| (cell.x, cell.y, cell.z));
+        actual.sort_by_key(|(cell, _)| (cell.x, cell.y, cell.z));
+        assert_eq!(actual, expected);
+
+        assert!(loaded_camera.translation.abs_diff_eq(camera.translation, 1e-5));
+        assert!(loaded_camera.rotation.abs_diff_eq(camera.rotation, 1e-5));
+
+        let loaded_gate = loaded_locations.locations["gate"];
+        assert_eq!(loaded_locations.locations.len(), 1);
+        assert_eq!(loaded_gate.position, gate.position);
+        assert_eq!(loaded_gate.yaw, gate.yaw);
+    }
+
+    // A one-block world with no camera or marks, for the repair cases to edit
+    fn minimal_save() -> SavedWorld {
+        SavedWorld {
+            version: SAVE_VERSION,
+            camera: None,
+            palette: vec!["glass".to_string(), "wood".to_string()],
+            blocks: vec![(IVec3::ZERO, 0)],
+            locations: Vec::new(),
+        }
+    }
+
+    type Loaded = (Option<Vec<String>>, VoxelWorld, NamedLocations, Transform);
+
+    // Loads `path` over a world holding one dirt block, a mark and a
+    // moved camera, so a failed load can be seen to leave them alone
+    fn load(path: &Path) -> Loaded {
+        let mut world = VoxelWorld::default();
+        world.set_block(IVec3::new(5, 5, 5), BlockType::Dirt);
+        let mut locations = NamedLocations::default();
+        let home = Location {
+            position: Vec3::ONE,
+            yaw: 0.0,
+        };
+        locations.locations.insert("home".to_string(), home);
+        let mut camera = Transform::from_xyz(0.0, 10.0, 0.0);
+
+        let repairs = load_from_file(path, &mut world, &mut locations, &mut camera);
+        fs::remove_file(path).ok();
+        (repairs, world, locations, camera)
+    }
+
+    fn load_saved(name: &str, saved: &SavedWorld) -> Loaded {
+        let path = temp_path(name);
+        write_saved(&path, saved);
+        load(&path)
+    }
+
+    fn assert_untouched(world: &VoxelWorld, locations: &NamedLocations, camera: &Transform) {
+        assert_eq!(world.blocks().collect::<Vec<_>>(), [(IVec3::new(5, 5, 5), BlockType::Dirt)]);
+        assert!(locations.locations.contains_key("home"));
+        assert_eq!(camera.translation, Vec3::new(0.0, 10.0, 0.0));
+    }
+
+    #[test]
+    fn unknown_block_names_fall_back_to_stone() {
+        let mut saved = minimal_save();
+        saved.palette[0] = "marble".to_string();
+
+        let (repairs, world, ..) = load_saved("unknown-name", &saved);
+        let repairs = repairs.unwrap();
+        assert_eq!(repairs.len(), 1);
+        assert!(repairs[0].contains("'marble'"), "{repairs:?}");
+        assert_eq!(world.get_block(IVec3::ZERO), Some(FALLBACK_BLOCK));
+    }
+
+    #[test]
+    fn blocks_without_a_palette_entry_fall_back_to_stone() {
+        let mut saved = minimal_save();
+        saved.blocks.push((IVec3::X, 7));
+
+        let (repairs, world, ..) = load_saved("bad-index", &saved);
+        assert_eq!(repairs.unwrap().len(), 1);
+        assert_eq!(world.get_block(IVec3::ZERO), Some(BlockType::Glass));
+        assert_eq!(world.get_block(IVec3::X), Some(FALLBACK_BLOCK));
+    }
+
+    #[test]
+    fn duplicate_cells_keep_the_last_entry() {
+        let mut saved = minimal_save();
+        saved.blocks.push((IVec3::ZERO, 1));
+
+        let (repairs, world, ..) = load_saved("duplicate", &saved);
+        assert_eq!(repairs.unwrap(), ["1 duplicate blocks dropped"]);
+        assert_eq!(world.block_count(), 1);
+        assert_eq!(world.get_block(IVec3::ZERO), Some(BlockType::Wood));
+    }
+
+    #[test]
+    fn invalid_camera_is_ignored() {
+        let mut saved = minimal_save();
+        saved.camera = Some(SavedCamera {
+            translation: Vec3::new(f32::INFINITY, 0.0, 0.0),
+            rotation: Quat::IDENTITY,
+        });
+
+        let (repairs, world, _, camera) = load_saved("bad-camera", &saved);
+        assert_eq!(repairs.unwrap(), ["invalid camera position ignored"]);
+        assert_eq!(world.get_block(IVec3::ZERO), Some(BlockType::Glass));
+        assert_eq!(camera.translation, Vec3::new(0.0, 10.0, 0.0));
+    }
+
+    #[test]
+    fn invalid_locations_are_dropped() {
+        let mut saved = minimal_save();
+        saved.locations = vec![
+            SavedLocation {
+                name: "gate".to_string(),
+                position: Vec3::new(1.0, 2.0, 3.0),
+                yaw: 0.5,
+            },
+            SavedLocation {
+                name: "nowhere".to_string(),
+                position: Vec3::ZERO,
+                yaw: f32::NAN,
+            },
+        ];
+
+        let (repairs, _, locations, _) = load_saved("bad-location", &saved);
+        assert_eq!(repairs.unwrap().len(), 1);
+        assert_eq!(locations.locations.keys().collect::<Vec<_>>(), ["gate"]);
+    }
+
+    #[test]
+    fn unsupported_versions_are_not_loaded() {
+        for version in [0, SAVE_VERSION + 1] {
+            let mut saved = minimal_save();
+            saved.version = version;
+
+            let (repairs, world, locations, camera) = load_saved("version", &saved);
+            assert_eq!(repairs, None);
+            assert_untouched(&world, &locations, &camera);
+        }
+    }
+
+    #[test]
+    fn malformed_files_are_not_loaded() {
+        let files = [("garbage", "not a world"), ("truncated", "(version: 2, palette: [")];
+        for (name, contents) in files {
+            let path = temp_path(name);
+            fs::write(&path, contents).unwrap();
+
+            let (repairs, world, locations, camera) = load(&path);
+            assert_eq!(repairs, None);
+            assert_untouched(&world, &locations, &camera);
+        }
+    }
+
+    #[test]
+    fn missing_files_are_not_loaded() {
+        let (repairs, world, locations, camera) = load(&temp_path("missing"));
+        assert_eq!(repairs, None);
+        assert_untouched(&world, &locations, &camera);
+    }
+
+    // Just what saving and loading with the keys needs, saving to `path`
+    fn save_world_app(path: PathBuf) -> World {
+        let mut world = World::new();
+        world.insert_resource(SaveSettings { path, ..default() });
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world.init_resource::<VoxelWorld>();
+        world.init_resource::<NamedLocations>();
+        world.init_resource::<UnsavedChanges>();
+        world.init_resource::<EditHistory>();
+        world.init_resource::<TerrainQueue>();
+        world.init_resource::<WorldMap>();
+        world.init_resource::<ChatState>();
+        world.init_resource::<Time>();
+        world.spawn((Camera3d::default(), Transform::default()));
+        world
+    }
+
+    fn press(world: &mut World, key: KeyCode) {
+        let mut keyboard = world.resource_mut::<ButtonInput<KeyCode>>();
+        keyboard.reset_all();
+        keyboard.press(key);
+        world.run_system_once(save_world).unwrap();
+        world.run_system_once(load_world).unwrap();
+    }
+
+    fn is_dirty(world: &World) -> bool {
+        let unsaved = world.resource::<UnsavedChanges>();
+        unsaved.is_dirty(world.resource::<VoxelWorld>(), world.resource::<NamedLocations>())
+    }
+
+    #[test]
+    fn edits_are_unsaved_until_the_next_save() {
+        let path = temp_path("dirty");
+        let mut world = save_world_app(path.clone());
+        assert!(!is_dirty(&world));
+
+        world.resource_mut::<VoxelWorld>().set_block(IVec3::ZERO, BlockType::Stone);
+        assert!(is_dirty(&world));
+        press(&mut world, KeyCode::F5);
+        assert!(!is_dirty(&world));
+
+        // Putting a block back the way it was still counts as an edit
+        world.resource_mut::<VoxelWorld>().remove_block(IVec3::ZERO);
+        world.resource_mut::<VoxelWorld>().set_block(IVec3::ZERO, BlockType::Stone);
+        assert!(is_dirty(&world));
+        press(&mut world, KeyCode::F5);
+        assert!(!is_dirty(&world));
+
+        let gate = Location { position: Vec3::ONE, yaw: 0.0 };
+        world.resource_mut::<NamedLocations>().locations.insert("gate".to_string(), gate);
+        assert!(is_dirty(&world));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn loading_replaces_unsaved_edits() {
+        let path = temp_path("dirty-load");
+        let mut world = save_world_app(path.clone());
+        world.resource_mut::<VoxelWorld>().set_block(IVec3::ZERO, BlockType::Stone);
+        press(&mut world, KeyCode::F5);
+
+        world.resource_mut::<VoxelWorld>().set_block(IVec3::X, BlockType::Wood);
+        assert!(is_dirty(&world));
+        press(&mut world, KeyCode::F9);
+        assert!(!is_dirty(&world));
+        assert_eq!(world.resource::<VoxelWorld>().get_block(IVec3::X), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn failed_saves_and_loads_leave_edits_unsaved() {
+        let path = temp_path("missing-dir").join("world.ron");
+        let mut world = save_world_app(path);
+        world.resource_mut::<VoxelWorld>().set_block(IVec3::ZERO, BlockType::Stone);
+
+        press(&mut world, KeyCode::F5);
+        assert!(is_dirty(&world));
+        press(&mut world, KeyCode::F9);
+        assert!(is_dirty(&world));
+        assert_eq!(world.resource::<VoxelWorld>().block_count(), 1);
+    }
+}
@@ -0,0 +1,47 @@
+use bevy::prelude::*;
+
+
+#[derive(Debug, Resource)]
+pub struct ScreenshotModeSettings {
+    pub toggle_key: KeyCode,
+}
+
+impl Default for ScreenshotModeSettings {
+    fn default() -> Self {
+        Self {
+            toggle_key: KeyCode::F1,
+        }
+    }
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct ScreenshotMode {
+    pub active: bool,
+}
+
+pub fn toggle_screenshot_mode(
+    settings: Res<ScreenshotModeSettings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut screenshot_mode: ResMut<ScreenshotMode>,
+) {
+    if keyboard.just_pressed(settings.toggle_key) {
+        screenshot_mode.active = !screenshot_mode.active;
+    }
+}
+
+// Hides every UI root at once so overlays added later are covered too.
+// Runs every frame so roots spawned while active stay hidden.
+pub fn apply_screenshot_mode(
+    screenshot_mode: Res<ScreenshotMode>,
+    mut ui_roots: Query<&mut Visibility, (With<Node>, Without<Parent>)>,
+) {
+    let visibility = if screenshot_mode.active {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    };
+
+    for mut root in &mut ui_roots {
+        root.set_if_neq(visibility);
+    }
+}
@@ -0,0 +1,471 @@
+use std::{collections::HashMap, env, fs, ops::RangeInclusive, path::PathBuf, str::FromStr};
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{
+    de::{value::StrDeserializer, IntoDeserializer},
+    Deserialize,
+};
+
+use crate::{
+    catalog::CatalogSettings,
+    chat::ChatSettings,
+    cursor::CursorSettings,
+    debug_overlay::DebugOverlaySettings,
+    flythrough::FlythroughSettings,
+    graphics::{Antialiasing, GraphicsSettings, QualityPreset},
+    hotbar::HotbarSettings,
+    idle::IdleSettings,
+    measure::MeasureSettings,
+    render_health::RenderHealthSettings,
+    save::SaveSettings,
+    screenshot_mode::ScreenshotModeSettings,
+    slice::SliceSettings,
+    spike_capture::SpikeCaptureSettings,
+    undo::UndoSettings,
+    BuildSettings, CameraSettings,
+};
+
+
+const SETTINGS_FILE: &str = "settings.toml";
+
+// Next to the executable so it travels with the build
+fn settings_path() -> PathBuf {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(SETTINGS_FILE)))
+        .unwrap_or_else(|| PathBuf::from(SETTINGS_FILE))
+}
+
+// Every settings resource the file can override
+#[derive(SystemParam)]
+pub struct Settings<'w> {
+    camera: ResMut<'w, CameraSettings>,
+    build: ResMut<'w, BuildSettings>,
+    save: ResMut<'w, SaveSettings>,
+    graphics: ResMut<'w, GraphicsSettings>,
+    spike_capture: ResMut<'w, SpikeCaptureSettings>,
+    undo: ResMut<'w, UndoSettings>,
+    slice: ResMut<'w, SliceSettings>,
+    measure: ResMut<'w, MeasureSettings>,
+    screenshot_mode: ResMut<'w, ScreenshotModeSettings>,
+    flythrough: ResMut<'w, FlythroughSettings>,
+    render_health: ResMut<'w, RenderHealthSettings>,
+    cursor: ResMut<'w, CursorSettings>,
+    hotbar: ResMut<'w, HotbarSettings>,
+    chat: ResMut<'w, ChatSettings>,
+    idle: ResMut<'w, IdleSettings>,
+    catalog: ResMut<'w, CatalogSettings>,
+    debug_overlay: ResMut<'w, DebugOverlaySettings>,
+}
+
+impl Settings<'_> {
+    // Everything bound to a key, by its name under `[keys]`
+    fn keys(&mut self) -> Vec<(&'static str, &mut KeyCode)> {
+        let camera = &mut *self.camera;
+        let build = &mut *self.build;
+        let save = &mut *self.save;
+        let graphics = &mut *self.graphics;
+        let undo = &mut *self.undo;
+        let measure = &mut *self.measure;
+        let flythrough = &mut *self.flythrough;
+
+        vec![
+            ("forward", &mut camera.forward_key),
+            ("back", &mut camera.back_key),
+            ("left", &mut camera.left_key),
+            ("right", &mut camera.right_key),
+            ("jump", &mut camera.jump_key),
+            ("descend", &mut camera.descend_key),
+            ("speed_up", &mut camera.speed_up_key),
+            ("speed_down", &mut camera.speed_down_key),
+            ("noclip", &mut camera.noclip_key),
+            ("walking", &mut camera.walking_key),
+            ("corner_assist", &mut build.corner_assist_key),
+            ("axis_lock", &mut build.axis_lock_key),
+            ("recent_blocks", &mut self.hotbar.recent_modifier),
+            ("catalog", &mut self.catalog.toggle_key),
+            ("undo", &mut undo.undo_key),
+            ("redo", &mut undo.redo_key),
+            ("slice", &mut self.slice.toggle_key),
+            ("measure", &mut measure.toggle_key),
+            ("measure_clear", &mut measure.clear_key),
+            ("chat", &mut self.chat.open_key),
+            ("release_cursor", &mut self.cursor.release_key),
+            ("save", &mut save.save_key),
+            ("load", &mut save.load_key),
+            ("screenshot_mode", &mut self.screenshot_mode.toggle_key),
+            ("flythrough_record", &mut flythrough.record_key),
+            ("flythrough_play", &mut flythrough.play_key),
+            ("flythrough_clear", &mut flythrough.clear_key),
+            ("flythrough_save", &mut flythrough.save_key),
+            ("flythrough_load", &mut flythrough.load_key),
+            ("antialiasing", &mut graphics.antialiasing_key),
+            ("sharpening", &mut graphics.sharpening_key),
+            ("rebuild_meshes", &mut self.render_health.rebuild_key),
+            ("debug_overlay", &mut self.debug_overlay.toggle_key),
+        ]
+    }
+
+    fn buttons(&mut self) -> Vec<(&'static str, &mut MouseButton)> {
+        let build = &mut *self.build;
+        let measure = &mut *self.measure;
+        vec![
+            ("place", &mut build.place_button),
+            ("remove", &mut build.remove_button),
+            ("measure_mark", &mut measure.mark_button),
+            ("measure_cancel", &mut measure.cancel_button),
+        ]
+    }
+}
+
+// Runs before anything reads the settings resources, so the file only
+// overrides their defaults
+pub fn load_settings(mut settings: Settings) {
+    let path = settings_path();
+
+    let Ok(contents) = fs::read_to_string(&path) else {
+        match fs::write(&path, default_contents(&mut settings)) {
+            Ok(()) => info!("Wrote default settings to {}", path.display()),
+            Err(err) => warn!("Failed to write default settings to {}: {err}", path.display()),
+        }
+        return;
+    };
+
+    apply_contents(&mut settings, &contents);
+    info!("Loaded settings from {}", path.display());
+}
+
+// One bad value only resets that value
+fn apply_contents(settings: &mut Settings, contents: &str) {
+    let fields = parse_fields(contents);
+    let camera = &mut *settings.camera;
+    let speed_range = camera.speed_range.start..=camera.speed_range.end;
+    read_field(&fields, "mouse_sensitivity", &mut camera.sensitivity, |value| {
+        parse_in_range(value, 0.0001..=0.1)
+    });
+    read_field(&fields, "invert_y", &mut camera.invert_y, parse_value);
+    read_field(&fields, "move_speed", &mut camera.speed, |value| {
+        parse_in_range(value, speed_range.clone())
+    });
+    read_field(&fields, "fov", &mut camera.fov, |value| parse_in_range(value, 10.0..=150.0));
+    read_field(&fields, "camera_smoothing", &mut camera.smoothing, |value| {
+        parse_in_range(value, 0.0..=5.0)
+    });
+    read_field(&fields, "reach", &mut settings.build.reach, |value| {
+        parse_in_range(value, 1.0..=64.0)
+    });
+    let graphics = &mut *settings.graphics;
+    // Files from before presets keep the values picked in them
+    if !fields.contains_key("quality") {
+        graphics.quality = None;
+    }
+    read_field(&fields, "quality", &mut graphics.quality, parse_quality);
+    // A preset decides everything it covers; the separate values are only
+    // read for "custom"
+    match graphics.quality {
+        Some(preset) => preset.apply(graphics),
+        None => {
+            let antialiasing = &mut graphics.antialiasing;
+            read_field(&fields, "antialiasing", antialiasing, Antialiasing::from_name);
+            read_field(&fields, "sharpening", &mut graphics.sharpening, parse_value);
+            read_field(&fields, "shadows", &mut graphics.shadows, parse_value);
+            read_field(&fields, "fog", &mut graphics.fog, parse_value);
+            read_field(&fields, "render_distance", &mut graphics.render_distance, |value| {
+                parse_in_range(value, 16.0..=10000.0)
+            });
+            read_field(&fields, "vsync", &mut graphics.vsync, parse_value);
+        }
+    }
+    read_field(&fields, "auto_quality", &mut graphics.auto_quality, parse_value);
+    let threshold = &mut graphics.auto_quality_threshold_ms;
+    read_field(&fields, "auto_quality_threshold_ms", threshold, |value| {
+        parse_in_range(value, 5.0..=1000.0)
+    });
+    read_field(&fields, "spike_capture", &mut settings.spike_capture.enabled, parse_value);
+    let idle = &mut *settings.idle;
+    read_field(&fields, "throttle_when_unfocused", &mut idle.throttle_when_unfocused, parse_value);
+    read_field(&fields, "unfocused_fps", &mut idle.unfocused_fps, |value| {
+        parse_in_range(value, 1.0..=240.0)
+    });
+    let release = &mut settings.cursor.release_on_focus_loss;
+    read_field(&fields, "release_cursor_on_focus_loss", release, parse_value);
+    let through_hidden = &mut settings.slice.target_through_hidden;
+    read_field(&fields, "slice_targets_hidden", through_hidden, parse_value);
+    read_field(&fields, "flythrough_duration", &mut settings.flythrough.duration, |value| {
+        parse_in_range(value, 0.5..=600.0)
+    });
+    for (name, key) in settings.keys() {
+        read_field(&fields, &format!("keys.{name}"), key, parse_key);
+    }
+    for (name, button) in settings.buttons() {
+        read_field(&fields, &format!("keys.{name}"), button, parse_button);
+    }
+}
+
+fn default_contents(settings: &mut Settings) -> String {
+    let (camera, build, graphics) = (&settings.camera, &settings.build, &settings.graphics);
+
+    let mut contents = String::new();
+    contents.push_str(&format!("mouse_sensitivity = {}\n", camera.sensitivity));
+    contents.push_str(&format!("invert_y = {}\n", camera.invert_y));
+    contents.push_str(&format!("move_speed = {}\n", camera.speed));
+    contents.push_str(&format!("reach = {}\n", build.reach));
+    contents.push_str(&format!("fov = {}\n", camera.fov));
+    contents.push_str(&format!("camera_smoothing = {}\n", camera.smoothing));
+    contents.push_str("# low, medium, high, or custom to use the values below it\n");
+    let quality = graphics.quality.map_or("custom", QualityPreset::name);
+    contents.push_str(&format!("quality = \"{quality}\"\n"));
+    contents.push_str("# off, msaa2, msaa4, fxaa or taa\n");
+    contents.push_str(&format!("antialiasing = \"{}\"\n", graphics.antialiasing.name()));
+    contents.push_str(&format!("sharpening = {}\n", graphics.sharpening));
+    contents.push_str(&format!("shadows = {}\n", graphics.shadows));
+    contents.push_str(&format!("fog = {}\n", graphics.fog));
+    contents.push_str(&format!("render_distance = {}\n", graphics.render_distance));
+    contents.push_str(&format!("vsync = {}\n", graphics.vsync));
+    contents.push_str("# Lower the quality preset a step when frames stay slower than the\n");
+    contents.push_str("# threshold for ten seconds, at most once a minute\n");
+    contents.push_str(&format!("auto_quality = {}\n", graphics.auto_quality));
+    let threshold = graphics.auto_quality_threshold_ms;
+    contents.push_str(&format!("auto_quality_threshold_ms = {threshold}\n"));
+    contents.push_str("# Write diagnostics to diagnostics/ when a frame takes far longer than\n");
+    contents.push_str("# usual, up to 3 files per session. Off by default.\n");
+    contents.push_str(&format!("spike_capture = {}\n", settings.spike_capture.enabled));
+    let (idle, cursor, slice) = (&settings.idle, &settings.cursor, &settings.slice);
+    contents.push_str("# Drop to unfocused_fps while the window is in the background\n");
+    contents.push_str(&format!("throttle_when_unfocused = {}\n", idle.throttle_when_unfocused));
+    contents.push_str(&format!("unfocused_fps = {}\n", idle.unfocused_fps));
+    let release = cursor.release_on_focus_loss;
+    contents.push_str(&format!("release_cursor_on_focus_loss = {release}\n"));
+    contents.push_str("# Let the crosshair reach through layers hidden by the slice view\n");
+    contents.push_str(&format!("slice_targets_hidden = {}\n", slice.target_through_hidden));
+    contents.push_str("# Seconds a flythrough takes from its first waypoint to its last\n");
+    contents.push_str(&format!("flythrough_duration = {}\n", settings.flythrough.duration));
+    contents.push_str("\n[keys]\n");
+    for (name, key) in settings.keys() {
+        contents.push_str(&format!("{name} = \"{key:?}\"\n"));
+    }
+    for (name, button) in settings.buttons() {
+        contents.push_str(&format!("{name} = \"{button:?}\"\n"));
+    }
+    contents
+}
+
+// Flat `key = value` lines with `[section]` headers, keyed as `section.key`
+fn parse_fields(contents: &str) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    let mut section = String::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            section = format!("{}.", name.trim());
+        } else if let Some((key, value)) = line.split_once('=') {
+            let value = value.trim().trim_matches('"');
+            fields.insert(format!("{section}{}", key.trim()), value.to_string());
+        } else {
+            warn!("Ignoring malformed settings line {}: {line}", number + 1);
+        }
+    }
+
+    fields
+}
+
+fn read_field<T>(
+    fields: &HashMap<String, String>,
+    name: &str,
+    target: &mut T,
+    parse: impl Fn(&str) -> Option<T>,
+) {
+    let Some(value) = fields.get(name) else {
+        return;
+    };
+
+    match parse(value) {
+        Some(value) => *target = value,
+        None => warn!("Invalid setting {name} = {value}, using default"),
+    }
+}
+
+fn parse_value<T: FromStr>(value: &str) -> Option<T> {
+    value.parse().ok()
+}
+
+// Out of range counts as invalid too. NaN and infinity are never in range,
+// and e.g. a zero field of view would break the projection.
+fn parse_in_range(value: &str, range: RangeInclusive<f32>) -> Option<f32> {
+    parse_value(value).filter(|value| range.contains(value))
+}
+
+// A preset name, or "custom" for None
+fn parse_quality(value: &str) -> Option<Option<QualityPreset>> {
+    match value {
+        "custom" => Some(None),
+        name => QualityPreset::from_name(name).map(Some),
+    }
+}
+
+// Key names are the `KeyCode` variant names, e.g. "KeyW" or "ShiftLeft"
+fn parse_key(value: &str) -> Option<KeyCode> {
+    let deserializer: StrDeserializer<serde::de::value::Error> = value.into_deserializer();
+    KeyCode::deserialize(deserializer).ok()
+}
+
+// Mouse buttons are the `MouseButton` variant names, e.g. "Left" or "Middle"
+fn parse_button(value: &str) -> Option<MouseButton> {
+    let deserializer: StrDeserializer<serde::de::value::Error> = value.into_deserializer();
+    MouseButton::deserialize(deserializer).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    fn settings_world() -> World {
+        let mut world = World::new();
+        world.init_resource::<CameraSettings>();
+        world.init_resource::<BuildSettings>();
+        world.init_resource::<SaveSettings>();
+        world.init_resource::<GraphicsSettings>();
+        world.init_resource::<SpikeCaptureSettings>();
+        world.init_resource::<UndoSettings>();
+        world.init_resource::<SliceSettings>();
+        world.init_resource::<MeasureSettings>();
+        world.init_resource::<ScreenshotModeSettings>();
+        world.init_resource::<FlythroughSettings>();
+        world.init_resource::<RenderHealthSettings>();
+        world.init_resource::<CursorSettings>();
+        world.init_resource::<HotbarSettings>();
+        world.init_resource::<ChatSettings>();
+        world.init_resource::<IdleSettings>();
+        world.init_resource::<CatalogSettings>();
+        world.init_resource::<DebugOverlaySettings>();
+        world
+    }
+
+    fn apply(world: &mut World, contents: &'static str) {
+        world
+            .run_system_once(move |mut settings: Settings| apply_contents(&mut settings, contents))
+            .unwrap();
+    }
+
+    #[test]
+    fn written_defaults_read_back_unchanged() {
+        let mut world = settings_world();
+        world.resource_mut::<IdleSettings>().unfocused_fps = 24.0;
+        world.resource_mut::<SliceSettings>().target_through_hidden = false;
+        world.resource_mut::<CameraSettings>().jump_key = KeyCode::KeyJ;
+        world.resource_mut::<FlythroughSettings>().duration = 25.0;
+        QualityPreset::Medium.apply(&mut world.resource_mut::<GraphicsSettings>());
+        let contents = world
+            .run_system_once(|mut settings: Settings| default_contents(&mut settings))
+            .unwrap();
+
+        let mut read_back = settings_world();
+        read_back
+            .run_system_once(move |mut settings: Settings| apply_contents(&mut settings, &contents))
+            .unwrap();
+        assert_eq!(read_back.resource::<IdleSettings>().unfocused_fps, 24.0);
+        assert!(!read_back.resource::<SliceSettings>().target_through_hidden);
+        assert_eq!(read_back.resource::<CameraSettings>().jump_key, KeyCode::KeyJ);
+        assert_eq!(read_back.resource::<FlythroughSettings>().duration, 25.0);
+        let graphics = read_back.resource::<GraphicsSettings>();
+        assert_eq!(graphics.quality, Some(QualityPreset::Medium));
+        assert_eq!(graphics.antialiasing, Antialiasing::Fxaa);
+    }
+
+    #[test]
+    fn background_behaviour_is_configurable() {
+        let mut world = settings_world();
+        apply(
+            &mut world,
+            "throttle_when_unfocused = false\n\
+             unfocused_fps = 30\n\
+             release_cursor_on_focus_loss = false\n\
+             slice_targets_hidden = false\n",
+        );
+
+        let idle = world.resource::<IdleSettings>();
+        assert!(!idle.throttle_when_unfocused);
+        assert_eq!(idle.unfocused_fps, 30.0);
+        assert!(!world.resource::<CursorSettings>().release_on_focus_loss);
+        assert!(!world.resource::<SliceSettings>().target_through_hidden);
+    }
+
+    #[test]
+    fn out_of_range_values_keep_their_defaults() {
+        let mut world = settings_world();
+        apply(
+            &mut world,
+            "mouse_sensitivity = NaN\n\
+             move_speed = -5\n\
+             fov = 0\n\
+             reach = inf\n\
+             camera_smoothing = -1\n\
+             unfocused_fps = 0\n\
+             flythrough_duration = 0\n",
+        );
+
+        let camera = world.resource::<CameraSettings>();
+        let defaults = CameraSettings::default();
+        assert_eq!(camera.sensitivity, defaults.sensitivity);
+        assert_eq!(camera.speed, defaults.speed);
+        assert_eq!(camera.fov, defaults.fov);
+        assert_eq!(camera.smoothing, defaults.smoothing);
+        assert_eq!(world.resource::<BuildSettings>().reach, BuildSettings::default().reach);
+        let unfocused_fps = world.resource::<IdleSettings>().unfocused_fps;
+        assert_eq!(unfocused_fps, IdleSettings::default().unfocused_fps);
+        let duration = world.resource::<FlythroughSettings>().duration;
+        assert_eq!(duration, FlythroughSettings::default().duration);
+    }
+
+    #[test]
+    fn in_range_values_are_applied() {
+        let mut world = settings_world();
+        apply(
+            &mut world,
+            "mouse_sensitivity = 0.005\n\
+             move_speed = 12.5\n\
+             fov = 90\n\
+             reach = 6\n\
+             flythrough_duration = 42.5\n",
+        );
+
+        let camera = world.resource::<CameraSettings>();
+        assert_eq!(camera.sensitivity, 0.005);
+        assert_eq!(camera.speed, 12.5);
+        assert_eq!(camera.fov, 90.0);
+        assert_eq!(world.resource::<BuildSettings>().reach, 6.0);
+        assert_eq!(world.resource::<FlythroughSettings>().duration, 42.5);
+    }
+
+    #[test]
+    fn quality_presets_override_the_separate_values() {
+        let mut world = settings_world();
+        apply(&mut world, "quality = \"low\"\nantialiasing = \"taa\"\nauto_quality = true\n");
+        let graphics = world.resource::<GraphicsSettings>();
+        assert_eq!(graphics.quality, Some(QualityPreset::Low));
+        assert_eq!(graphics.antialiasing, Antialiasing::Off);
+        assert!(graphics.auto_quality);
+
+        let mut world = settings_world();
+        apply(&mut world, "quality = \"custom\"\nantialiasing = \"taa\"\nrender_distance = 300\n");
+        let graphics = world.resource::<GraphicsSettings>();
+        assert_eq!(graphics.quality, None);
+        assert_eq!(graphics.antialiasing, Antialiasing::Taa);
+        assert_eq!(graphics.render_distance, 300.0);
+    }
+
+    #[test]
+    fn files_without_a_quality_keep_their_values() {
+        let mut world = settings_world();
+        apply(&mut world, "antialiasing = \"fxaa\"\n");
+        let graphics = world.resource::<GraphicsSettings>();
+        assert_eq!(graphics.quality, None);
+        assert_eq!(graphics.antialiasing, Antialiasing::Fxaa);
+    }
+}
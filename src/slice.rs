@@ -0,0 +1,103 @@
+use std::f32::consts::FRAC_PI_2;
+use bevy::{input::mouse::MouseWheel, prelude::*};
+
+use crate::{screenshot_mode::ScreenshotMode, voxel::VoxelWorld};
+
+
+const GRID_CELLS: u32 = 32;
+const GRID_COLOR: Color = Color::srgba(1.0, 1.0, 1.0, 0.15);
+
+#[derive(Debug, Resource)]
+pub struct SliceSettings {
+    pub toggle_key: KeyCode,
+    // Let the crosshair reach through hidden blocks to the exposed layer
+    pub target_through_hidden: bool,
+}
+
+impl Default for SliceSettings {
+    fn default() -> Self {
+        Self {
+            toggle_key: KeyCode::KeyL,
+            target_through_hidden: true,
+        }
+    }
+}
+
+// Hides every layer above `cutoff` from rendering. The blocks stay in the
+// world, so collision and saving are unaffected. Local to this client.
+#[derive(Debug, Default, Resource)]
+pub struct SliceView {
+    pub active: bool,
+    pub cutoff: i32,
+}
+
+impl SliceView {
+    // Highest layer still drawn, if any are hidden
+    pub fn ceiling(&self) -> Option<i32> {
+        self.active.then_some(self.cutoff)
+    }
+}
+
+// Scrolling moves the cutoff while slice view is on
+pub fn update_slice(
+    camera_query: Query<&Transform, With<Camera>>,
+    settings: Res<SliceSettings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    mut slice: ResMut<SliceView>,
+    mut world: ResMut<VoxelWorld>,
+) {
+    if keyboard.just_pressed(settings.toggle_key) {
+        if slice.active {
+            slice.active = false;
+            world.mark_layers_dirty(slice.cutoff..=i32::MAX);
+        } else {
+            // Start just below eye level, which cuts off the roof overhead
+            slice.active = true;
+            slice.cutoff = camera_query.single().translation.y.floor() as i32 - 1;
+            world.mark_layers_dirty(slice.cutoff..=i32::MAX);
+        }
+    }
+
+    if !slice.active {
+        mouse_wheel.clear();
+        return;
+    }
+
+    let mut cutoff = slice.cutoff;
+    for event in mouse_wheel.read() {
+        if event.y > 0.0 {
+            cutoff += 1;
+        } else if event.y < 0.0 {
+            cutoff -= 1;
+        }
+    }
+
+    if cutoff != slice.cutoff {
+        world.mark_layers_dirty(cutoff.min(slice.cutoff)..=cutoff.max(slice.cutoff));
+        slice.cutoff = cutoff;
+    }
+}
+
+// Faint grid on top of the cutoff layer, following the camera
+pub fn draw_slice_plane(
+    camera_query: Query<&Transform, With<Camera>>,
+    slice: Res<SliceView>,
+    screenshot_mode: Res<ScreenshotMode>,
+    mut gizmos: Gizmos,
+) {
+    if !slice.active || screenshot_mode.active {
+        return;
+    }
+
+    let camera = camera_query.single().translation.floor();
+    gizmos.grid(
+        Isometry3d::new(
+            Vec3::new(camera.x, (slice.cutoff + 1) as f32, camera.z),
+            Quat::from_rotation_x(FRAC_PI_2),
+        ),
+        UVec2::splat(GRID_CELLS),
+        Vec2::ONE,
+        GRID_COLOR,
+    );
+}
@@ -0,0 +1,302 @@
+use std::{
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use bevy::prelude::*;
+
+use crate::{budget::FrameBudget, voxel::VoxelWorld};
+
+
+// Frames kept before a spike, and the baseline the spike is measured against
+const WINDOW: usize = 120;
+// Budgeted systems timed per frame, highest priority first
+const MAX_TIMED_SYSTEMS: usize = 8;
+
+#[derive(Debug, Resource)]
+pub struct SpikeCaptureSettings {
+    pub enabled: bool,
+    // A frame this many times the recent median counts as a spike
+    pub threshold: f32,
+    pub max_captures: usize,
+    pub directory: PathBuf,
+}
+
+// Off unless turned on in the settings file, so nothing is written to disk
+// behind the player's back
+impl Default for SpikeCaptureSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 3.0,
+            max_captures: 3,
+            directory: PathBuf::from("diagnostics"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct FrameSample {
+    frame_ms: f32,
+    blocks: usize,
+    dirty_chunks: usize,
+    // Time each budgeted system spent, in `FrameBudget::systems` order
+    system_ms: [f32; MAX_TIMED_SYSTEMS],
+}
+
+// Ring buffer of recent frames. Everything is allocated up front so
+// recording a frame costs nothing beyond a few copies.
+#[derive(Debug, Resource)]
+pub struct SpikeCapture {
+    samples: Vec<FrameSample>,
+    next: usize,
+    filled: bool,
+    // Scratch space for finding the median without allocating
+    sorted: Vec<f32>,
+    captures: usize,
+    // Set while the window is unfocused. The app is throttled then, and the
+    // first frame after refocus carries the whole throttled wait.
+    skip_next: bool,
+}
+
+impl Default for SpikeCapture {
+    fn default() -> Self {
+        Self {
+            samples: vec![FrameSample::default(); WINDOW],
+            next: 0,
+            filled: false,
+            sorted: vec![0.0; WINDOW],
+            captures: 0,
+            skip_next: false,
+        }
+    }
+}
+
+impl SpikeCapture {
+    fn median_frame_ms(&mut self) -> f32 {
+        for (sorted, sample) in self.sorted.iter_mut().zip(&self.samples) {
+            *sorted = sample.frame_ms;
+        }
+        self.sorted.sort_unstable_by(f32::total_cmp);
+        self.sorted[WINDOW / 2]
+    }
+
+    // Oldest first
+    fn window(&self) -> impl Iterator<Item = &FrameSample> {
+        self.samples[self.next..].iter().chain(&self.samples[..self.next])
+    }
+
+    // The median the sample was measured against if it's a spike worth
+    // capturing. Compared against the frames before this one, so the spike
+    // itself can't raise its own baseline.
+    fn check(&mut self, settings: &SpikeCaptureSettings, sample: FrameSample) -> Option<f32> {
+        if !self.filled || self.captures >= settings.max_captures {
+            return None;
+        }
+
+        let median = self.median_frame_ms();
+        if sample.frame_ms <= median * settings.threshold {
+            return None;
+        }
+        self.captures += 1;
+        Some(median)
+    }
+
+    fn push(&mut self, sample: FrameSample) {
+        self.samples[self.next] = sample;
+        self.next = (self.next + 1) % WINDOW;
+        if self.next == 0 {
+            self.filled = true;
+        }
+    }
+}
+
+pub fn capture_spikes(
+    settings: Res<SpikeCaptureSettings>,
+    mut capture: ResMut<SpikeCapture>,
+    world: Res<VoxelWorld>,
+    budget: Res<FrameBudget>,
+    windows: Query<&Window>,
+    entities: Query<Entity>,
+    time: Res<Time>,
+) {
+    if !settings.enabled {
+        return;
+    }
+
+    if !windows.iter().any(|window| window.focused) {
+        capture.skip_next = true;
+        return;
+    }
+    if std::mem::take(&mut capture.skip_next) {
+        return;
+    }
+
+    let mut sample = FrameSample {
+        frame_ms: time.delta_secs() * 1000.0,
+        blocks: world.block_count(),
+        dirty_chunks: world.dirty_chunk_count(),
+        system_ms: [0.0; MAX_TIMED_SYSTEMS],
+    };
+    for (ms, system) in sample.system_ms.iter_mut().zip(budget.systems()) {
+        *ms = system.used.as_secs_f32() * 1000.0;
+    }
+
+    if let Some(median) = capture.check(&settings, sample) {
+        write_capture(&settings, &capture, &budget, sample, median, entities.iter().count());
+    }
+    capture.push(sample);
+}
+
+fn write_capture(
+    settings: &SpikeCaptureSettings,
+    capture: &SpikeCapture,
+    budget: &FrameBudget,
+    spike: FrameSample,
+    median: f32,
+    entities: usize,
+) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default();
+    let path = settings.directory.join(format!("spike-{timestamp}.toml"));
+
+    let mut contents = String::new();
+    contents.push_str("[spike]\n");
+    contents.push_str(&format!("frame_ms = {}\n", spike.frame_ms));
+    contents.push_str(&format!("median_ms = {median}\n"));
+    contents.push_str(&format!("entities = {entities}\n"));
+    contents.push_str(&format!("blocks = {}\n", spike.blocks));
+    contents.push_str(&format!("dirty_chunks = {}\n", spike.dirty_chunks));
+    let systems = budget.systems().iter().take(MAX_TIMED_SYSTEMS).enumerate();
+    for (index, system) in systems.clone() {
+        contents.push_str(&format!("{}_ms = {}\n", system.name, spike.system_ms[index]));
+    }
+    contents.push_str("\n[window]\n");
+    contents.push_str("# Oldest first, ending with the frame before the spike\n");
+    let column = |value: &dyn Fn(&FrameSample) -> String| {
+        capture.window().map(value).collect::<Vec<_>>().join(", ")
+    };
+    contents.push_str(&format!("frame_ms = [{}]\n", column(&|sample| sample.frame_ms.to_string())));
+    contents.push_str(&format!("blocks = [{}]\n", column(&|sample| sample.blocks.to_string())));
+    let dirty_chunks = column(&|sample| sample.dirty_chunks.to_string());
+    contents.push_str(&format!("dirty_chunks = [{dirty_chunks}]\n"));
+    for (index, system) in systems {
+        let times = column(&|sample| sample.system_ms[index].to_string());
+        contents.push_str(&format!("{}_ms = [{times}]\n", system.name));
+    }
+
+    let result = fs::create_dir_all(&settings.directory).and_then(|()| fs::write(&path, contents));
+    match result {
+        Ok(()) => warn!(
+            "Frame took {:.1} ms against a {median:.1} ms median, wrote {}",
+            spike.frame_ms,
+            path.display()
+        ),
+        Err(err) => error!("Failed to write {}: {err}", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(frame_ms: f32) -> FrameSample {
+        let mut system_ms = [0.0; MAX_TIMED_SYSTEMS];
+        system_ms[..2].copy_from_slice(&[1.5, 0.25]);
+        FrameSample {
+            frame_ms,
+            blocks: 500,
+            dirty_chunks: 2,
+            system_ms,
+        }
+    }
+
+    fn steady_capture() -> SpikeCapture {
+        let mut capture = SpikeCapture::default();
+        for _ in 0..WINDOW {
+            capture.push(frame(16.0));
+        }
+        capture
+    }
+
+    #[test]
+    fn spikes_are_measured_against_the_median_once_the_window_is_full() {
+        let settings = SpikeCaptureSettings::default();
+        let mut capture = SpikeCapture::default();
+        for _ in 0..WINDOW - 1 {
+            capture.push(frame(16.0));
+        }
+        assert_eq!(capture.check(&settings, frame(100.0)), None);
+
+        capture.push(frame(16.0));
+        assert_eq!(capture.check(&settings, frame(40.0)), None);
+        assert_eq!(capture.check(&settings, frame(100.0)), Some(16.0));
+    }
+
+    #[test]
+    fn captures_stop_at_the_maximum() {
+        let settings = SpikeCaptureSettings {
+            max_captures: 2,
+            ..default()
+        };
+        let mut capture = steady_capture();
+        assert!(capture.check(&settings, frame(100.0)).is_some());
+        assert!(capture.check(&settings, frame(100.0)).is_some());
+        assert_eq!(capture.check(&settings, frame(100.0)), None);
+    }
+
+    #[test]
+    fn a_spike_writes_a_well_formed_capture_file() {
+        let name = format!("castle_wars-spikes-{}", std::process::id());
+        let directory = std::env::temp_dir().join(name);
+        let settings = SpikeCaptureSettings {
+            enabled: true,
+            directory: directory.clone(),
+            ..default()
+        };
+        let mut capture = steady_capture();
+        let spike = frame(100.0);
+        let median = capture.check(&settings, spike).unwrap();
+        write_capture(&settings, &capture, &FrameBudget::default(), spike, median, 42);
+
+        let files = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        let contents = fs::read_to_string(&files[0]).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(files.len(), 1);
+        let name = files[0].file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("spike-") && name.ends_with(".toml"), "{name}");
+
+        // Every line is a section header, a comment or a key = value pair
+        let mut sections = Vec::new();
+        let mut values = Vec::new();
+        for line in contents.lines().filter(|line| !line.is_empty()) {
+            if let Some(section) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                sections.push(section);
+            } else if !line.starts_with('#') {
+                let (key, value) = line.split_once(" = ").expect(line);
+                values.push((sections.len(), key, value));
+            }
+        }
+        assert_eq!(sections, ["spike", "window"]);
+        assert!(values.contains(&(1, "frame_ms", "100")));
+        assert!(values.contains(&(1, "median_ms", "16")));
+        assert!(values.contains(&(1, "entities", "42")));
+        assert!(values.contains(&(1, "meshing_ms", "1.5")));
+        assert!(values.contains(&(1, "terrain_ms", "0.25")));
+
+        for key in ["frame_ms", "blocks", "dirty_chunks", "meshing_ms", "terrain_ms"] {
+            let (.., column) = values
+                .iter()
+                .find(|(section, name, _)| *section == 2 && *name == key)
+                .unwrap();
+            let column = column.strip_prefix('[').and_then(|column| column.strip_suffix(']'));
+            assert_eq!(column.unwrap().split(", ").count(), WINDOW, "{key}");
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use bevy::prelude::*;
+
+use crate::{
+    collision::CollisionShape,
+    hotbar::SelectedBlock,
+    placement_cell,
+    screenshot_mode::ScreenshotMode,
+    slice::{SliceSettings, SliceView},
+    voxel::{cell_center, BlockAssets, BlockType, VoxelHit, VoxelWorld},
+    BuildSettings, CameraSettings,
+};
+
+
+const HIGHLIGHT_COLOR: Color = Color::srgb(0.1, 0.1, 0.1);
+const CROSSHAIR_SIZE: f32 = 16.0;
+const CROSSHAIR_THICKNESS: f32 = 2.0;
+
+// What the crosshair points at, refreshed once per frame so placement,
+// removal and measuring all act on the same hit
+#[derive(Debug, Default, Resource)]
+pub struct Target {
+    pub hit: Option<VoxelHit>,
+    // Cell a left click would fill, already checked against blocks and the player
+    pub placement: Option<IVec3>,
+}
+
+// Row of cells placement is held to while the axis lock key is down.
+// Set by the first placement after pressing it.
+#[derive(Debug, Default, Resource)]
+pub struct LineLock(pub Option<Line>);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Line {
+    pub start: IVec3,
+    pub axis: usize,
+    pub block: BlockType,
+}
+
+impl Line {
+    // Runs along whichever horizontal axis the camera faces most
+    pub fn facing(start: IVec3, forward: Vec3, block: BlockType) -> Self {
+        let axis = if forward.x.abs() >= forward.z.abs() { 0 } else { 2 };
+        Self { start, axis, block }
+    }
+
+    // Pulls a cell that's one step off the row back onto it, so aim
+    // drifting a little while building doesn't bend the line. One step
+    // includes diagonals across the row, e.g. one up and one sideways when
+    // the aim slips onto the top of a block beside the row. Works in both
+    // directions along the row.
+    pub fn snap(self, cell: IVec3) -> Option<IVec3> {
+        let mut snapped = self.start;
+        snapped[self.axis] = cell[self.axis];
+        ((cell - snapped).abs().max_element() <= 1).then_some(snapped)
+    }
+}
+
+// See-through copy of the selected block showing where it will go.
+// Spawned once and moved around rather than respawned every frame.
+#[derive(Component)]
+pub struct PlacementGhost;
+
+// The material is filled in by `update_placement_ghost` once block
+// assets exist
+pub fn setup_placement_ghost(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.spawn((
+        Name::new("Placement Ghost"),
+        PlacementGhost,
+        Mesh3d(meshes.add(Cuboid::default())),
+        MeshMaterial3d::<StandardMaterial>::default(),
+        Transform::default(),
+        Visibility::Hidden,
+    ));
+}
+
+pub fn setup_crosshair(mut commands: Commands) {
+    commands
+        .spawn((
+            Name::new("Crosshair"),
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    width: Val::Px(CROSSHAIR_SIZE),
+                    height: Val::Px(CROSSHAIR_SIZE),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    let offset = Val::Px((CROSSHAIR_SIZE - CROSSHAIR_THICKNESS) * 0.5);
+                    parent.spawn((
+                        Node {
+                            position_type: PositionType::Absolute,
+                            left: offset,
+                            width: Val::Px(CROSSHAIR_THICKNESS),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        BackgroundColor(Color::WHITE.with_alpha(0.8)),
+                    ));
+                    parent.spawn((
+                        Node {
+                            position_type: PositionType::Absolute,
+                            top: offset,
+                            width: Val::Percent(100.0),
+                            height: Val::Px(CROSSHAIR_THICKNESS),
+                            ..default()
+                        },
+                        BackgroundColor(Color::WHITE.with_alpha(0.8)),
+                    ));
+                });
+        });
+}
+
+// The cursor is locked and hidden, so its position says nothing about
+// where the player is looking; aim from the middle of the view instead
+pub fn camera_ray(camera_transform: &GlobalTransform) -> Ray3d {
+    Ray3d::new(camera_transform.translation(), camera_transform.forward())
+}
+
+pub fn update_target(
+    camera_query: Query<(&GlobalTransform, &CollisionShape), With<Camera>>,
+    world: Res<VoxelWorld>,
+    build_settings: Res<BuildSettings>,
+    camera_settings: Res<CameraSettings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    slice_settings: Res<SliceSettings>,
+    slice: Res<SliceView>,
+    line_lock: Res<LineLock>,
+    mut target: ResMut<Target>,
+) {
+    let (camera_transform, shape) = camera_query.single();
+    let ray = camera_ray(camera_transform);
+    let assist = build_settings.corner_assist_always || keyboard.pressed(build_settings.corner_assist_key);
+    let ceiling = slice.ceiling().filter(|_| slice_settings.target_through_hidden);
+
+    target.hit = world.raycast_below(ray.origin, *ray.direction, build_settings.reach, ceiling);
+    target.placement = target.hit.and_then(|hit| {
+        placement_cell(
+            ray,
+            hit,
+            assist,
+            line_lock.0,
+            &build_settings,
+            &camera_settings,
+            *shape,
+            &world,
+        )
+    });
+}
+
+// The lock lasts while the key is held and the same block type is selected
+pub fn update_line_lock(
+    settings: Res<BuildSettings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    selected: Res<SelectedBlock>,
+    mut line_lock: ResMut<LineLock>,
+) {
+    let keep = line_lock
+        .0
+        .is_some_and(|line| keyboard.pressed(settings.axis_lock_key) && line.block == selected.0);
+    if line_lock.0.is_some() && !keep {
+        line_lock.0 = None;
+    }
+}
+
+pub fn draw_target_highlight(
+    target: Res<Target>,
+    screenshot_mode: Res<ScreenshotMode>,
+    mut gizmos: Gizmos,
+) {
+    if screenshot_mode.active {
+        return;
+    }
+
+    if let Some(hit) = target.hit {
+        gizmos.cuboid(
+            Transform::from_translation(cell_center(hit.cell)).with_scale(Vec3::splat(1.01)),
+            HIGHLIGHT_COLOR,
+        );
+    }
+}
+
+pub fn update_placement_ghost(
+    target: Res<Target>,
+    selected: Res<SelectedBlock>,
+    block_assets: Res<BlockAssets>,
+    screenshot_mode: Res<ScreenshotMode>,
+    mut ghost_query: Query<
+        (&mut Transform, &mut Visibility, &mut MeshMaterial3d<StandardMaterial>),
+        With<PlacementGhost>,
+    >,
+) {
+    let (mut transform, mut visibility, mut material) = ghost_query.single_mut();
+
+    let ghost_material = &block_assets.ghost_materials[&selected.0];
+    if material.0 != *ghost_material {
+        material.0 = ghost_material.clone();
+    }
+
+    match target.placement {
+        Some(cell) if !screenshot_mode.active => {
+            transform.translation = cell_center(cell);
+            visibility.set_if_neq(Visibility::Inherited);
+        }
+        _ => {
+            visibility.set_if_neq(Visibility::Hidden);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aim(world: &VoxelWorld, eye: Vec3, target: Vec3) -> (Ray3d, VoxelHit) {
+        let ray = Ray3d::new(eye, Dir3::new(target - eye).unwrap());
+        let hit = world.raycast(ray.origin, *ray.direction, 10.0).unwrap();
+        (ray, hit)
+    }
+
+    // Walks a bridge out along +x next to a parapet one row over, aiming
+    // at the front edge of the last block with corner assist held. The
+    // aim wobbles sideways, and every third block it slips onto the top
+    // of the parapet, which without the lock would put the block on the
+    // parapet instead.
+    #[test]
+    fn twenty_block_bridge_stays_on_its_row_while_aim_wobbles() {
+        let mut world = VoxelWorld::default();
+        for x in -1..=21 {
+            world.set_block(IVec3::new(x, 10, 1), BlockType::Stone);
+        }
+        let start = IVec3::new(0, 10, 0);
+        world.set_block(start, BlockType::Wood);
+        let line = Line::facing(start, Vec3::X, BlockType::Wood);
+        let build_settings = BuildSettings::default();
+        let camera_settings = CameraSettings::default();
+        let shape = CollisionShape::default();
+
+        let mut bridge = vec![start];
+        for step in 1..=20 {
+            let wobble = (step as f32 * 1.7).sin() * 0.15;
+            let last = *bridge.last().unwrap();
+            let eye = last.as_vec3() + Vec3::new(-0.5, 2.6, 0.5 + wobble);
+            let target = if step % 3 == 0 {
+                last.as_vec3() + Vec3::new(1.5, 1.0, 1.5 - wobble)
+            } else {
+                last.as_vec3() + Vec3::new(0.85, 1.0, 0.5 - wobble)
+            };
+            let (ray, hit) = aim(&world, eye, target);
+
+            let place = |line| {
+                placement_cell(
+                    ray,
+                    hit,
+                    true,
+                    line,
+                    &build_settings,
+                    &camera_settings,
+                    shape,
+                    &world,
+                )
+            };
+            if step % 3 == 0 {
+                assert_eq!(place(None), Some(last + IVec3::new(1, 1, 1)));
+            }
+            let cell = place(Some(line)).unwrap();
+            world.set_block(cell, BlockType::Wood);
+            bridge.push(cell);
+        }
+
+        // 21 distinct cells, all on the starting block's row
+        for (x, cell) in bridge.iter().enumerate() {
+            assert_eq!(*cell, start + IVec3::X * x as i32);
+        }
+    }
+
+    // A cell off the row by one both vertically and sideways still snaps.
+    // "One step off" means any cell around the row in its cross-section,
+    // since aim slipping onto a neighbouring top face moves both at once.
+    #[test]
+    fn snap_pulls_diagonal_drift_onto_the_row() {
+        let line = Line::facing(IVec3::ZERO, Vec3::X, BlockType::Stone);
+        for drift in [IVec3::new(0, 1, 1), IVec3::new(0, 1, -1), IVec3::new(0, -1, 1)] {
+            assert_eq!(line.snap(IVec3::X * 7 + drift), Some(IVec3::X * 7));
+        }
+    }
+
+    #[test]
+    fn snap_continues_the_row_backwards() {
+        let line = Line::facing(IVec3::ZERO, Vec3::NEG_Z, BlockType::Stone);
+        assert_eq!(line.axis, 2);
+        assert_eq!(line.snap(IVec3::new(1, 0, 5)), Some(IVec3::new(0, 0, 5)));
+        assert_eq!(line.snap(IVec3::new(-1, -1, -5)), Some(IVec3::new(0, 0, -5)));
+    }
+
+    #[test]
+    fn snap_refuses_cells_well_off_the_row() {
+        let line = Line::facing(IVec3::ZERO, Vec3::X, BlockType::Stone);
+        assert_eq!(line.snap(IVec3::new(4, 0, 2)), None);
+        assert_eq!(line.snap(IVec3::new(4, -2, 0)), None);
+    }
+}
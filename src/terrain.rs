@@ -0,0 +1,336 @@
+use std::{env, time::{SystemTime, UNIX_EPOCH}};
+use bevy::{math::FloatExt, prelude::*};
+
+use crate::{
+    budget::FrameBudget,
+    landmarks::{LandmarkRegistry, WorldMap},
+    voxel::{BlockType, VoxelWorld, CHUNK_EDGE},
+};
+
+
+// Keeps the cave noise apart from the surface octaves on the same seed
+const CAVE_SEED: u64 = 0xCA7E_0000_0000_0000;
+
+#[derive(Debug, Resource)]
+pub struct TerrainSettings {
+    // The same seed always produces the same terrain
+    pub seed: u64,
+    // Columns of chunks generated in each direction from the origin
+    pub radius: i32,
+    // Surface heights fall between these, inclusive
+    pub min_height: i32,
+    pub max_height: i32,
+    // Noise features per block of the first octave
+    pub frequency: f32,
+    pub octaves: u32,
+    pub dirt_depth: i32,
+    // Stone is carved out wherever 3D noise at `cave_scale` features per
+    // block goes over `cave_threshold`, which is in 0..1
+    pub caves: bool,
+    pub cave_scale: f32,
+    pub cave_threshold: f32,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            radius: 4,
+            min_height: 2,
+            max_height: 18,
+            frequency: 1.0 / 48.0,
+            octaves: 4,
+            dirt_depth: 3,
+            caves: true,
+            cave_scale: 1.0 / 12.0,
+            cave_threshold: 0.7,
+        }
+    }
+}
+
+impl TerrainSettings {
+    // `--seed <n>` picks the seed; otherwise each run gets a fresh one
+    pub fn from_args() -> Self {
+        let mut settings = Self {
+            seed: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_nanos() as u64)
+                .unwrap_or_default(),
+            ..default()
+        };
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg == "--seed" {
+                match args.next().map(|seed| seed.parse()) {
+                    Some(Ok(seed)) => settings.seed = seed,
+                    // Logging isn't set up yet this early
+                    _ => eprintln!("--seed needs a whole number"),
+                }
+            }
+        }
+        settings
+    }
+}
+
+// Chunk columns still to generate, nearest the origin last so they're
+// popped first
+#[derive(Debug, Default, Resource)]
+pub struct TerrainQueue(Vec<IVec2>);
+
+impl TerrainQueue {
+    pub fn around_origin(radius: i32) -> Self {
+        let mut columns = Vec::new();
+        for x in -radius..radius {
+            for z in -radius..radius {
+                columns.push(IVec2::new(x, z));
+            }
+        }
+        columns.sort_by_key(|column| -(*column * 2 + IVec2::ONE).length_squared());
+        Self(columns)
+    }
+}
+
+// Spread over frames within the terrain budget so startup isn't held up.
+// Landmarks go on top once their column's terrain is in.
+pub fn generate_terrain(
+    settings: Res<TerrainSettings>,
+    landmarks: Res<LandmarkRegistry>,
+    mut queue: ResMut<TerrainQueue>,
+    mut world: ResMut<VoxelWorld>,
+    mut map: ResMut<WorldMap>,
+    mut budget: ResMut<FrameBudget>,
+) {
+    if queue.0.is_empty() {
+        return;
+    }
+
+    let mut work = budget.slice("terrain");
+    while work.has_time() {
+        let Some(column) = queue.0.pop() else {
+            return;
+        };
+
+        for (cell, block) in generate_chunk(column, &settings) {
+            world.set_block(cell, block);
+        }
+        if let Some((origin, landmark)) = landmarks.landmark_in(column, &settings) {
+            for (cell, block) in landmark.template.place(origin) {
+                world.set_block(cell, block);
+            }
+            map.landmarks.insert(origin, landmark.name.clone());
+        }
+        work.item_done();
+    }
+}
+
+// Every block of one column of chunks: stone, then dirt, then a grass top,
+// with caves carved out of the stone. Everything only depends on world
+// coordinates, so neighbouring columns meet without seams.
+pub fn generate_chunk(column: IVec2, settings: &TerrainSettings) -> Vec<(IVec3, BlockType)> {
+    let origin = column * CHUNK_EDGE;
+    let mut blocks = Vec::new();
+
+    for x in origin.x..origin.x + CHUNK_EDGE {
+        for z in origin.y..origin.y + CHUNK_EDGE {
+            let height = surface_height(settings, x, z);
+            for y in 0..=height {
+                // The bottom layer and the dirt stay whole, so caves never
+                // open into the void or onto the surface
+                let cell = IVec3::new(x, y, z);
+                let underground = y > 0 && y < height - settings.dirt_depth;
+                if underground && is_cave(settings, cell) {
+                    continue;
+                }
+                let block = if y == height {
+                    BlockType::Grass
+                } else if y >= height - settings.dirt_depth {
+                    BlockType::Dirt
+                } else {
+                    BlockType::Stone
+                };
+                blocks.push((cell, block));
+            }
+        }
+    }
+
+    blocks
+}
+
+// Layered value noise, each octave at twice the frequency and half the
+// weight of the one before
+pub fn surface_height(settings: &TerrainSettings, x: i32, z: i32) -> i32 {
+    let position = Vec2::new(x as f32, z as f32);
+    let mut total = 0.0;
+    let mut weight = 1.0;
+    let mut weights = 0.0;
+    let mut frequency = settings.frequency;
+
+    for octave in 0..settings.octaves.max(1) {
+        let seed = settings.seed.wrapping_add(octave as u64);
+        total += value_noise(seed, position * frequency) * weight;
+        weights += weight;
+        weight *= 0.5;
+        frequency *= 2.0;
+    }
+
+    let range = (settings.max_height - settings.min_height) as f32;
+    settings.min_height + (total / weights * range).round() as i32
+}
+
+fn is_cave(settings: &TerrainSettings, cell: IVec3) -> bool {
+    settings.caves
+        && value_noise_3d(settings.seed ^ CAVE_SEED, cell.as_vec3() * settings.cave_scale)
+            > settings.cave_threshold
+}
+
+// Independent 2D noise on each whole y, blended between the layers above
+// and below
+fn value_noise_3d(seed: u64, position: Vec3) -> f32 {
+    let lattice = position.y.floor();
+    let fraction = position.y - lattice;
+    let t = fraction * fraction * (3.0 - 2.0 * fraction);
+    let layer = |y: i32| {
+        let seed = seed ^ (y as u32 as u64).wrapping_mul(0xD6E8_FEB8_6659_FD93);
+        value_noise(seed, position.xz())
+    };
+    layer(lattice as i32).lerp(layer(lattice as i32 + 1), t)
+}
+
+// Smoothly interpolated random values on an integer lattice, in 0..1
+fn value_noise(seed: u64, position: Vec2) -> f32 {
+    let lattice = position.floor();
+    let fraction = position - lattice;
+    let t = fraction * fraction * (Vec2::splat(3.0) - 2.0 * fraction);
+    let (x, z) = (lattice.x as i32, lattice.y as i32);
+
+    let top = lattice_value(seed, x, z).lerp(lattice_value(seed, x + 1, z), t.x);
+    let bottom = lattice_value(seed, x, z + 1).lerp(lattice_value(seed, x + 1, z + 1), t.x);
+    top.lerp(bottom, t.y)
+}
+
+// SplitMix64 finaliser over the seed and the packed lattice point
+pub fn lattice_value(seed: u64, x: i32, z: i32) -> f32 {
+    let mut hash = seed ^ (((x as u32 as u64) << 32) | z as u32 as u64);
+    hash = hash.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    hash ^= hash >> 31;
+    (hash >> 40) as f32 / (1u64 << 24) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn heights(column: IVec2, settings: &TerrainSettings) -> HashMap<IVec2, i32> {
+        let mut heights = HashMap::new();
+        for (cell, _) in generate_chunk(column, settings) {
+            let height = heights.entry(cell.xz()).or_insert(cell.y);
+            *height = (*height).max(cell.y);
+        }
+        heights
+    }
+
+    #[test]
+    fn same_seed_same_terrain() {
+        let settings = TerrainSettings { seed: 42, ..default() };
+        let column = IVec2::new(-3, 2);
+        assert_eq!(generate_chunk(column, &settings), generate_chunk(column, &settings));
+
+        let other = TerrainSettings { seed: 43, ..default() };
+        assert_ne!(generate_chunk(column, &settings), generate_chunk(column, &other));
+    }
+
+    #[test]
+    fn columns_are_layered_within_the_height_range() {
+        let settings = TerrainSettings { seed: 7, caves: false, ..default() };
+        let blocks: HashMap<IVec3, BlockType> =
+            generate_chunk(IVec2::ZERO, &settings).into_iter().collect();
+
+        for (position, height) in heights(IVec2::ZERO, &settings) {
+            assert!((settings.min_height..=settings.max_height).contains(&height));
+            for y in 0..=height {
+                let expected = if y == height {
+                    BlockType::Grass
+                } else if y >= height - settings.dirt_depth {
+                    BlockType::Dirt
+                } else {
+                    BlockType::Stone
+                };
+                assert_eq!(blocks[&IVec3::new(position.x, y, position.y)], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn neighbouring_chunks_meet_without_seams() {
+        let settings = TerrainSettings { seed: 99, ..default() };
+        let mut all = HashMap::new();
+        for x in -1..=1 {
+            for z in -1..=1 {
+                let column = heights(IVec2::new(x, z), &settings);
+                assert_eq!(column.len(), (CHUNK_EDGE * CHUNK_EDGE) as usize);
+                for (position, height) in column {
+                    assert!(all.insert(position, height).is_none(), "{position} generated twice");
+                }
+            }
+        }
+
+        // A step across a chunk border is no bigger than the largest step
+        // inside one
+        let step = |a: IVec2, b: IVec2| (all[&a] - all[&b]).abs();
+        let mut inside = 0;
+        let mut across = 0;
+        for (&a, &height) in &all {
+            assert_eq!(height, surface_height(&settings, a.x, a.y));
+            for offset in [IVec2::X, IVec2::Y] {
+                let b = a + offset;
+                if !all.contains_key(&b) {
+                    continue;
+                }
+                if (b * offset).element_sum().rem_euclid(CHUNK_EDGE) == 0 {
+                    across = across.max(step(a, b));
+                } else {
+                    inside = inside.max(step(a, b));
+                }
+            }
+        }
+        assert!(across <= inside, "border step {across}, inside step {inside}");
+    }
+
+    #[test]
+    fn caves_only_appear_below_the_surface() {
+        let settings = TerrainSettings { seed: 5, ..default() };
+        let mut carved = 0;
+        for x in -1..=1 {
+            for z in -1..=1 {
+                let column = IVec2::new(x, z);
+                let blocks: HashMap<IVec3, BlockType> =
+                    generate_chunk(column, &settings).into_iter().collect();
+
+                for (position, height) in heights(column, &settings) {
+                    assert_eq!(height, surface_height(&settings, position.x, position.y));
+                    for y in 0..=height {
+                        let cell = IVec3::new(position.x, y, position.y);
+                        if blocks.contains_key(&cell) {
+                            continue;
+                        }
+                        carved += 1;
+                        assert!(y > 0, "{cell} carved through the bottom");
+                        assert!(y < height - settings.dirt_depth, "{cell} carved near the surface");
+                    }
+                }
+            }
+        }
+        assert!(carved > 0, "no caves at all");
+
+        // Turning them off fills every column back in
+        let solid = TerrainSettings { caves: false, ..settings };
+        let column = generate_chunk(IVec2::ZERO, &solid);
+        let expected = heights(IVec2::ZERO, &solid).values().map(|height| height + 1).sum::<i32>();
+        assert_eq!(column.len(), expected as usize);
+    }
+}
@@ -0,0 +1,238 @@
+use bevy::prelude::*;
+
+use crate::{
+    history::{BoundedHistory, HistoryUsage},
+    voxel::{BlockType, VoxelWorld},
+};
+
+
+const MAX_EDITS: usize = 256;
+
+#[derive(Debug, Resource)]
+pub struct UndoSettings {
+    // Pressed together with Ctrl; Ctrl+Shift+undo_key also redoes
+    pub undo_key: KeyCode,
+    pub redo_key: KeyCode,
+}
+
+impl Default for UndoSettings {
+    fn default() -> Self {
+        Self {
+            undo_key: KeyCode::KeyZ,
+            redo_key: KeyCode::KeyY,
+        }
+    }
+}
+
+// A single cell changing from `before` to `after`, None being empty
+#[derive(Debug, Clone, Copy)]
+pub struct BlockEdit {
+    pub cell: IVec3,
+    pub before: Option<BlockType>,
+    pub after: Option<BlockType>,
+}
+
+impl BlockEdit {
+    fn inverse(self) -> Self {
+        Self {
+            cell: self.cell,
+            before: self.after,
+            after: self.before,
+        }
+    }
+}
+
+#[derive(Debug, Resource)]
+pub struct EditHistory {
+    undo: BoundedHistory<BlockEdit>,
+    redo: Vec<BlockEdit>,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self {
+            undo: BoundedHistory::new(MAX_EDITS),
+            redo: Vec::new(),
+        }
+    }
+}
+
+impl EditHistory {
+    // A new edit makes anything undone before it unreachable
+    pub fn record(&mut self, edit: BlockEdit) {
+        self.undo.push(edit);
+        self.redo.clear();
+    }
+
+    // For when the world is replaced wholesale, e.g. by loading a save
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    pub fn usage(&self) -> HistoryUsage {
+        self.undo.usage()
+    }
+}
+
+pub fn undo_redo(
+    settings: Res<UndoSettings>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut history: ResMut<EditHistory>,
+    mut world: ResMut<VoxelWorld>,
+) {
+    if !keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+
+    let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if keyboard.just_pressed(settings.redo_key) || (shift && keyboard.just_pressed(settings.undo_key)) {
+        if let Some(edit) = history.redo.pop() {
+            if apply_edit(&mut world, edit) {
+                history.undo.push(edit);
+            }
+        }
+    } else if keyboard.just_pressed(settings.undo_key) {
+        if let Some(edit) = history.undo.pop() {
+            if apply_edit(&mut world, edit.inverse()) {
+                history.redo.push(edit);
+            }
+        }
+    }
+}
+
+// Refuses edits whose starting state no longer matches the world, so a
+// stale entry can't clobber a block that got there some other way
+fn apply_edit(world: &mut VoxelWorld, edit: BlockEdit) -> bool {
+    if world.get_block(edit.cell) != edit.before {
+        warn!("Dropping edit at {}: the cell has changed since", edit.cell);
+        return false;
+    }
+
+    match edit.after {
+        Some(block) => world.set_block(edit.cell, block),
+        None => {
+            world.remove_block(edit.cell);
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    fn undo_world() -> World {
+        let mut world = World::new();
+        world.init_resource::<UndoSettings>();
+        world.init_resource::<EditHistory>();
+        world.init_resource::<VoxelWorld>();
+        world.init_resource::<ButtonInput<KeyCode>>();
+        world
+    }
+
+    // Places or removes like the build systems do, recording the edit
+    fn edit(world: &mut World, cell: IVec3, after: Option<BlockType>) {
+        let before = world.resource::<VoxelWorld>().get_block(cell);
+        let edit = BlockEdit { cell, before, after };
+        assert!(apply_edit(&mut world.resource_mut::<VoxelWorld>(), edit));
+        world.resource_mut::<EditHistory>().record(edit);
+    }
+
+    fn press(world: &mut World, keys: &[KeyCode]) {
+        let mut keyboard = world.resource_mut::<ButtonInput<KeyCode>>();
+        keyboard.reset_all();
+        for &key in keys {
+            keyboard.press(key);
+        }
+        world.run_system_once(undo_redo).unwrap();
+    }
+
+    fn undo(world: &mut World) {
+        press(world, &[KeyCode::ControlLeft, KeyCode::KeyZ]);
+    }
+
+    fn redo(world: &mut World) {
+        press(world, &[KeyCode::ControlLeft, KeyCode::KeyY]);
+    }
+
+    fn block(world: &World, cell: IVec3) -> Option<BlockType> {
+        world.resource::<VoxelWorld>().get_block(cell)
+    }
+
+    #[test]
+    fn undo_and_redo_a_placement_and_a_removal() {
+        let mut world = undo_world();
+        edit(&mut world, IVec3::ZERO, Some(BlockType::Wood));
+        edit(&mut world, IVec3::ZERO, None);
+
+        undo(&mut world);
+        assert_eq!(block(&world, IVec3::ZERO), Some(BlockType::Wood));
+        undo(&mut world);
+        assert_eq!(block(&world, IVec3::ZERO), None);
+
+        redo(&mut world);
+        assert_eq!(block(&world, IVec3::ZERO), Some(BlockType::Wood));
+        // Ctrl+Shift+Z redoes too
+        press(&mut world, &[KeyCode::ControlLeft, KeyCode::ShiftLeft, KeyCode::KeyZ]);
+        assert_eq!(block(&world, IVec3::ZERO), None);
+    }
+
+    #[test]
+    fn keys_need_ctrl() {
+        let mut world = undo_world();
+        edit(&mut world, IVec3::ZERO, Some(BlockType::Stone));
+
+        press(&mut world, &[KeyCode::KeyZ]);
+        assert_eq!(block(&world, IVec3::ZERO), Some(BlockType::Stone));
+    }
+
+    #[test]
+    fn a_new_edit_clears_redo() {
+        let mut world = undo_world();
+        edit(&mut world, IVec3::ZERO, Some(BlockType::Stone));
+        undo(&mut world);
+        edit(&mut world, IVec3::X, Some(BlockType::Glass));
+
+        redo(&mut world);
+        assert_eq!(block(&world, IVec3::ZERO), None);
+        assert_eq!(block(&world, IVec3::X), Some(BlockType::Glass));
+    }
+
+    #[test]
+    fn history_keeps_the_newest_edits() {
+        let mut world = undo_world();
+        for x in 0..MAX_EDITS as i32 + 10 {
+            edit(&mut world, IVec3::new(x, 0, 0), Some(BlockType::Stone));
+        }
+
+        for _ in 0..MAX_EDITS + 10 {
+            undo(&mut world);
+        }
+        let voxels = world.resource::<VoxelWorld>();
+        assert_eq!(voxels.block_count(), 10);
+        assert_eq!(voxels.get_block(IVec3::new(9, 0, 0)), Some(BlockType::Stone));
+        assert_eq!(voxels.get_block(IVec3::new(10, 0, 0)), None);
+    }
+
+    #[test]
+    fn stale_edits_are_refused() {
+        let mut world = undo_world();
+        edit(&mut world, IVec3::ZERO, Some(BlockType::Stone));
+        // Something other than an edit, e.g. a block event, replaces it
+        world.resource_mut::<VoxelWorld>().set_block(IVec3::ZERO, BlockType::Wood);
+
+        undo(&mut world);
+        assert_eq!(block(&world, IVec3::ZERO), Some(BlockType::Wood));
+        // The refused edit is gone rather than parked on the redo stack
+        redo(&mut world);
+        assert_eq!(block(&world, IVec3::ZERO), Some(BlockType::Wood));
+
+        let mut voxels = VoxelWorld::default();
+        let edit = BlockEdit { cell: IVec3::ZERO, before: Some(BlockType::Dirt), after: None };
+        assert!(!apply_edit(&mut voxels, edit));
+        assert_eq!(voxels.block_count(), 0);
+    }
+}
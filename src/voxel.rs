@@ -0,0 +1,674 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::RangeInclusive,
+};
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology},
+        render_asset::RenderAssetUsages,
+    },
+};
+
+use crate::{budget::FrameBudget, slice::SliceView};
+
+
+// Blocks are meshed in cubes of this many cells per side
+pub const CHUNK_EDGE: i32 = 16;
+const GHOST_ALPHA: f32 = 0.4;
+
+// (normal, u, v) with u x v == normal, so quads wind counter-clockwise from outside
+const FACES: [(IVec3, IVec3, IVec3); 6] = [
+    (IVec3::X, IVec3::Y, IVec3::Z),
+    (IVec3::NEG_X, IVec3::Z, IVec3::Y),
+    (IVec3::Y, IVec3::Z, IVec3::X),
+    (IVec3::NEG_Y, IVec3::X, IVec3::Z),
+    (IVec3::Z, IVec3::X, IVec3::Y),
+    (IVec3::NEG_Z, IVec3::Y, IVec3::X),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockType {
+    Stone,
+    Sandstone,
+    Wood,
+    Grass,
+    Dirt,
+    Glass,
+}
+
+impl BlockType {
+    // Hotbar order
+    pub const ALL: [BlockType; 6] = [
+        BlockType::Stone,
+        BlockType::Sandstone,
+        BlockType::Wood,
+        BlockType::Grass,
+        BlockType::Dirt,
+        BlockType::Glass,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BlockType::Stone => "stone",
+            BlockType::Sandstone => "sandstone",
+            BlockType::Wood => "wood",
+            BlockType::Grass => "grass",
+            BlockType::Dirt => "dirt",
+            BlockType::Glass => "glass",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<BlockType> {
+        BlockType::ALL.into_iter().find(|block| block.name() == name)
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            BlockType::Stone => Color::srgb(0.5, 0.5, 0.52),
+            BlockType::Sandstone => Color::srgb(0.8, 0.7, 0.6),
+            BlockType::Wood => Color::srgb(0.55, 0.38, 0.2),
+            BlockType::Grass => Color::srgb(0.3, 0.6, 0.25),
+            BlockType::Dirt => Color::srgb(0.45, 0.32, 0.2),
+            BlockType::Glass => Color::srgba(0.7, 0.85, 0.9, 0.35),
+        }
+    }
+
+    pub fn is_transparent(self) -> bool {
+        self == BlockType::Glass
+    }
+
+    pub fn material(self) -> StandardMaterial {
+        StandardMaterial {
+            base_color: self.color(),
+            alpha_mode: if self.is_transparent() {
+                AlphaMode::Blend
+            } else {
+                AlphaMode::Opaque
+            },
+            ..default()
+        }
+    }
+
+    // Same lighting response as the placed block, only see-through, so
+    // the preview shows the colour the block will really have
+    fn ghost_material(self) -> StandardMaterial {
+        let color = self.color();
+        StandardMaterial {
+            base_color: color.with_alpha(color.alpha() * GHOST_ALPHA),
+            alpha_mode: AlphaMode::Blend,
+            ..self.material()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct VoxelHit {
+    pub cell: IVec3,
+    // Face of `cell` the ray entered through
+    pub normal: IVec3,
+    pub distance: f32,
+}
+
+// Cell `c` covers the unit cube from `c` to `c + 1`
+#[derive(Debug, Default, Resource)]
+pub struct VoxelWorld {
+    blocks: HashMap<IVec3, BlockType>,
+    // Chunks whose mesh no longer matches the block data
+    dirty_chunks: HashSet<IVec3>,
+    // Bumped by every change to the blocks, so saving can tell whether
+    // anything changed since
+    revision: u64,
+}
+
+impl VoxelWorld {
+    pub fn get_block(&self, cell: IVec3) -> Option<BlockType> {
+        self.blocks.get(&cell).copied()
+    }
+
+    pub fn set_block(&mut self, cell: IVec3, block: BlockType) {
+        if self.blocks.insert(cell, block) != Some(block) {
+            self.mark_dirty(cell);
+            self.revision += 1;
+        }
+    }
+
+    pub fn remove_block(&mut self, cell: IVec3) -> Option<BlockType> {
+        let removed = self.blocks.remove(&cell);
+        if removed.is_some() {
+            self.mark_dirty(cell);
+            self.revision += 1;
+        }
+        removed
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn dirty_chunk_count(&self) -> usize {
+        self.dirty_chunks.len()
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = (IVec3, BlockType)> + '_ {
+        self.blocks.iter().map(|(cell, block)| (*cell, *block))
+    }
+
+    pub fn clear(&mut self) {
+        let cells = self.blocks.keys().copied().collect::<Vec<_>>();
+        for cell in cells {
+            self.mark_dirty(cell);
+            self.revision += 1;
+        }
+        self.blocks.clear();
+    }
+
+    // For chunks whose mesh was lost rather than made stale by an edit
+    pub fn mark_chunk_dirty(&mut self, chunk: IVec3) {
+        self.dirty_chunks.insert(chunk);
+    }
+
+    // For view changes that alter how whole layers are drawn without
+    // touching the blocks in them
+    pub fn mark_layers_dirty(&mut self, layers: RangeInclusive<i32>) {
+        self.dirty_chunks.extend(
+            self.blocks
+                .keys()
+                .filter(|cell| layers.contains(&cell.y))
+                .map(|cell| chunk_of(*cell)),
+        );
+    }
+
+    // A cell on a chunk boundary also dirties the neighbour, whose face
+    // against this cell may have just been exposed or covered
+    fn mark_dirty(&mut self, cell: IVec3) {
+        let chunk = chunk_of(cell);
+        let local = cell - chunk * CHUNK_EDGE;
+        self.dirty_chunks.insert(chunk);
+
+        for axis in 0..3 {
+            let mut neighbour = chunk;
+            if local[axis] == 0 {
+                neighbour[axis] -= 1;
+            } else if local[axis] == CHUNK_EDGE - 1 {
+                neighbour[axis] += 1;
+            } else {
+                continue;
+            }
+            self.dirty_chunks.insert(neighbour);
+        }
+    }
+
+    // Amanatides-Woo grid traversal. The cell containing `origin` is skipped
+    // so a camera inside a block can still target what's in front of it.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<VoxelHit> {
+        self.raycast_below(origin, direction, max_distance, None)
+    }
+
+    // Like `raycast`, but passes through blocks above `ceiling`
+    pub fn raycast_below(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        ceiling: Option<i32>,
+    ) -> Option<VoxelHit> {
+        let mut cell = world_to_cell(origin);
+        let mut step = IVec3::ZERO;
+        let mut t_max = Vec3::splat(f32::INFINITY);
+        let mut t_delta = Vec3::splat(f32::INFINITY);
+
+        for axis in 0..3 {
+            let d = direction[axis];
+            if d > 0.0 {
+                step[axis] = 1;
+                t_delta[axis] = 1.0 / d;
+                t_max[axis] = (cell[axis] as f32 + 1.0 - origin[axis]) / d;
+            } else if d < 0.0 {
+                step[axis] = -1;
+                t_delta[axis] = -1.0 / d;
+                t_max[axis] = (origin[axis] - cell[axis] as f32) / -d;
+            }
+        }
+
+        loop {
+            // On exact ties step along Y first, so looking almost straight
+            // down onto an edge picks the top face rather than a side
+            let axis = if t_max.y <= t_max.x && t_max.y <= t_max.z {
+                1
+            } else if t_max.x <= t_max.z {
+                0
+            } else {
+                2
+            };
+
+            let distance = t_max[axis];
+            if distance > max_distance {
+                return None;
+            }
+
+            cell[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+
+            if self.blocks.contains_key(&cell) && ceiling.is_none_or(|ceiling| cell.y <= ceiling) {
+                let mut normal = IVec3::ZERO;
+                normal[axis] = -step[axis];
+                return Some(VoxelHit { cell, normal, distance });
+            }
+        }
+    }
+}
+
+// One material per block type, shared by every chunk mesh
+#[derive(Debug, Resource)]
+pub struct BlockAssets {
+    pub materials: HashMap<BlockType, Handle<StandardMaterial>>,
+    // For the placement preview
+    pub ghost_materials: HashMap<BlockType, Handle<StandardMaterial>>,
+    // Shown instead of a block material that went missing
+    pub error_material: Handle<StandardMaterial>,
+}
+
+impl BlockAssets {
+    pub fn new(materials: &mut Assets<StandardMaterial>) -> Self {
+        Self {
+            materials: BlockType::ALL
+                .into_iter()
+                .map(|block| (block, materials.add(block.material())))
+                .collect(),
+            ghost_materials: BlockType::ALL
+                .into_iter()
+                .map(|block| (block, materials.add(block.ghost_material())))
+                .collect(),
+            error_material: materials.add(StandardMaterial {
+                base_color: Color::srgb(1.0, 0.0, 1.0),
+                unlit: true,
+                ..default()
+            }),
+        }
+    }
+}
+
+// Each chunk gets one mesh entity per block type it contains
+#[derive(Debug, Default, Resource)]
+pub struct ChunkEntities(HashMap<(IVec3, BlockType), (Entity, Handle<Mesh>)>);
+
+impl ChunkEntities {
+    pub fn iter(&self) -> impl Iterator<Item = (IVec3, BlockType, Entity, &Handle<Mesh>)> {
+        self.0
+            .iter()
+            .map(|((chunk, block), (entity, mesh))| (*chunk, *block, *entity, mesh))
+    }
+}
+
+pub fn cell_center(cell: IVec3) -> Vec3 {
+    cell.as_vec3() + Vec3::splat(0.5)
+}
+
+pub fn world_to_cell(position: Vec3) -> IVec3 {
+    position.floor().as_ivec3()
+}
+
+pub fn chunk_of(cell: IVec3) -> IVec3 {
+    cell.div_euclid(IVec3::splat(CHUNK_EDGE))
+}
+
+#[derive(Default)]
+struct ChunkGeometry {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl ChunkGeometry {
+    fn push_face(&mut self, base: IVec3, normal: IVec3, u: IVec3, v: IVec3) {
+        let start = self.positions.len() as u32;
+        for corner in [base, base + u, base + u + v, base + v] {
+            self.positions.push(corner.as_vec3().to_array());
+            self.normals.push(normal.as_vec3().to_array());
+        }
+        self.uvs.extend([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+        self.indices.extend([start, start + 1, start + 2, start, start + 2, start + 3]);
+    }
+
+    fn into_mesh(self) -> Mesh {
+        Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals)
+            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs)
+            .with_inserted_indices(Indices::U32(self.indices))
+    }
+}
+
+// A face is hidden behind an opaque neighbour, or between two blocks of
+// the same transparent type so glass walls don't show inner faces
+fn face_hidden(block: BlockType, neighbour: Option<BlockType>) -> bool {
+    neighbour.is_some_and(|neighbour| !neighbour.is_transparent() || neighbour == block)
+}
+
+// Only visible faces are emitted, including across chunk boundaries.
+// Block types without a visible face in the chunk are left out. Cells
+// above `ceiling` are treated as empty.
+pub fn build_chunk_meshes(
+    world: &VoxelWorld,
+    chunk: IVec3,
+    ceiling: Option<i32>,
+) -> HashMap<BlockType, Mesh> {
+    let origin = chunk * CHUNK_EDGE;
+    let mut geometry = HashMap::<BlockType, ChunkGeometry>::new();
+    let get_block = |cell: IVec3| {
+        world
+            .get_block(cell)
+            .filter(|_| ceiling.is_none_or(|ceiling| cell.y <= ceiling))
+    };
+
+    for x in 0..CHUNK_EDGE {
+        for y in 0..CHUNK_EDGE {
+            for z in 0..CHUNK_EDGE {
+                let local = IVec3::new(x, y, z);
+                let Some(block) = get_block(origin + local) else {
+                    continue;
+                };
+
+                for (normal, u, v) in FACES {
+                    if face_hidden(block, get_block(origin + local + normal)) {
+                        continue;
+                    }
+
+                    let base = local + normal.max(IVec3::ZERO);
+                    geometry.entry(block).or_default().push_face(base, normal, u, v);
+                }
+            }
+        }
+    }
+
+    geometry
+        .into_iter()
+        .map(|(block, geometry)| (block, geometry.into_mesh()))
+        .collect()
+}
+
+// Runs after every system that edits blocks so each dirty chunk is
+// rebuilt at most once per frame. Chunks nearest the camera go first and
+// whatever doesn't fit in the frame budget waits, so big changes like
+// loading a world or leaving slice view are spread over frames.
+pub fn rebuild_chunk_meshes(
+    mut commands: Commands,
+    camera_query: Query<&GlobalTransform, With<Camera>>,
+    mut world: ResMut<VoxelWorld>,
+    mut chunk_entities: ResMut<ChunkEntities>,
+    block_assets: Res<BlockAssets>,
+    slice: Res<SliceView>,
+    mut budget: ResMut<FrameBudget>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    if world.dirty_chunks.is_empty() {
+        return;
+    }
+
+    let camera_chunk = chunk_of(world_to_cell(camera_query.single().translation()));
+    let mut dirty_chunks = world.dirty_chunks.iter().copied().collect::<Vec<_>>();
+    dirty_chunks.sort_by_key(|chunk| (*chunk - camera_chunk).length_squared());
+
+    let mut work = budget.slice("meshing");
+    for chunk in dirty_chunks {
+        if !work.has_time() {
+            break;
+        }
+        world.dirty_chunks.remove(&chunk);
+        let mut chunk_meshes = build_chunk_meshes(&world, chunk, slice.ceiling());
+
+        for block in BlockType::ALL {
+            let key = (chunk, block);
+            match (chunk_meshes.remove(&block), chunk_entities.0.get(&key)) {
+                (Some(mesh), Some((_, handle))) => {
+                    meshes.insert(handle, mesh);
+                }
+                (Some(mesh), None) => {
+                    let handle = meshes.add(mesh);
+                    let entity = commands
+                        .spawn((
+                            Name::new("Chunk"),
+                            Mesh3d(handle.clone()),
+                            MeshMaterial3d(block_assets.materials[&block].clone()),
+                            Transform::from_translation((chunk * CHUNK_EDGE).as_vec3()),
+                        ))
+                        .id();
+                    chunk_entities.0.insert(key, (entity, handle));
+                }
+                (None, Some(_)) => {
+                    if let Some((entity, handle)) = chunk_entities.0.remove(&key) {
+                        commands.entity(entity).despawn();
+                        meshes.remove(&handle);
+                    }
+                }
+                (None, None) => {}
+            }
+        }
+        work.item_done();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world_with(cells: &[IVec3]) -> VoxelWorld {
+        let mut world = VoxelWorld::default();
+        for cell in cells {
+            world.set_block(*cell, BlockType::Stone);
+        }
+        world
+    }
+
+    // Flat square of blocks with its corner at `corner`
+    fn floor(corner: IVec3, size: i32) -> VoxelWorld {
+        let mut world = VoxelWorld::default();
+        for x in 0..size {
+            for z in 0..size {
+                world.set_block(corner + IVec3::new(x, 0, z), BlockType::Stone);
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn world_to_cell_floors_negative_coordinates() {
+        assert_eq!(world_to_cell(Vec3::new(-0.5, -1.5, -2.25)), IVec3::new(-1, -2, -3));
+        assert_eq!(world_to_cell(Vec3::new(-0.001, -15.999, -16.001)), IVec3::new(-1, -16, -17));
+        assert_eq!(world_to_cell(Vec3::new(0.999, 0.001, 15.5)), IVec3::new(0, 0, 15));
+    }
+
+    #[test]
+    fn world_to_cell_keeps_whole_coordinates_in_their_own_cell() {
+        assert_eq!(world_to_cell(Vec3::ZERO), IVec3::ZERO);
+        assert_eq!(world_to_cell(Vec3::new(-0.0, -0.0, -0.0)), IVec3::ZERO);
+        assert_eq!(world_to_cell(Vec3::new(1.0, 2.0, 3.0)), IVec3::new(1, 2, 3));
+        assert_eq!(world_to_cell(Vec3::new(-1.0, -2.0, -3.0)), IVec3::new(-1, -2, -3));
+    }
+
+    #[test]
+    fn cell_center_lies_in_its_cell() {
+        for cell in [IVec3::ZERO, IVec3::new(-1, -1, -1), IVec3::new(7, -20, 300)] {
+            assert_eq!(world_to_cell(cell_center(cell)), cell);
+        }
+    }
+
+    #[test]
+    fn chunk_of_rounds_towards_negative_infinity() {
+        assert_eq!(chunk_of(IVec3::new(0, 15, 16)), IVec3::new(0, 0, 1));
+        assert_eq!(chunk_of(IVec3::new(-1, -16, -17)), IVec3::new(-1, -1, -2));
+    }
+
+    #[test]
+    fn raycast_parallel_to_each_axis() {
+        let origin = Vec3::splat(0.5);
+        for direction in [IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z] {
+            let block = direction * 3;
+            let world = world_with(&[block]);
+
+            let hit = world.raycast(origin, direction.as_vec3(), 10.0).unwrap();
+            assert_eq!(hit.cell, block);
+            assert_eq!(hit.normal, -direction);
+            assert_eq!(hit.distance, 2.5);
+        }
+    }
+
+    #[test]
+    fn raycast_never_steps_along_axes_without_direction() {
+        // Just beside the ray. A zero component keeps that axis's t_max
+        // infinite, so these are never entered however far the ray goes.
+        let world = world_with(&[
+            IVec3::new(3, 1, 0),
+            IVec3::new(3, -1, 0),
+            IVec3::new(3, 0, 1),
+            IVec3::new(3, 0, -1),
+        ]);
+        assert!(world.raycast(Vec3::new(0.5, 0.999, 0.001), Vec3::X, 10.0).is_none());
+        assert!(world.raycast(Vec3::new(0.5, 0.001, 0.999), Vec3::X, 10.0).is_none());
+    }
+
+    #[test]
+    fn raycast_straight_down_onto_the_ground() {
+        let world = floor(IVec3::new(-2, 0, -2), 5);
+        let origin = Vec3::new(0.3, 5.0, -0.7);
+
+        let hit = world.raycast(origin, Vec3::NEG_Y, 10.0).unwrap();
+        assert_eq!(hit.cell, IVec3::new(0, 0, -1));
+        assert_eq!(hit.normal, IVec3::Y);
+        assert_eq!(hit.distance, 4.0);
+
+        // Out of reach
+        assert!(world.raycast(origin + Vec3::Y * 10.0, Vec3::NEG_Y, 10.0).is_none());
+    }
+
+    // 2x2 blocks from (0, 0, 0) to (2, 1, 2). Aiming exactly at the edges
+    // and the corner they share from all around has to land on a top face,
+    // in a cell whose top face contains the hit point.
+    #[test]
+    fn raycast_on_shared_edges_and_corners() {
+        let world = floor(IVec3::ZERO, 2);
+        let targets = [Vec3::new(1.0, 1.0, 0.5), Vec3::new(0.5, 1.0, 1.0), Vec3::new(1.0, 1.0, 1.0)];
+        let eyes = [
+            Vec3::new(1.0, 4.0, 1.0),
+            Vec3::new(-2.0, 3.0, 0.5),
+            Vec3::new(4.0, 3.0, 0.5),
+            Vec3::new(0.5, 3.0, -2.0),
+            Vec3::new(0.5, 3.0, 4.0),
+            Vec3::new(-1.5, 2.5, -1.5),
+            Vec3::new(3.5, 2.5, 3.5),
+            Vec3::new(3.0, 1.5, -1.0),
+        ];
+        let on_face = |value: f32| (-1e-4..=1.0 + 1e-4).contains(&value);
+
+        for target in targets {
+            for eye in eyes {
+                let direction = (target - eye).normalize();
+                let hit = world.raycast(eye, direction, 20.0).unwrap();
+                let point = eye + direction * hit.distance;
+                let local = point - hit.cell.as_vec3();
+
+                assert_eq!(hit.normal, IVec3::Y, "{eye} -> {target}");
+                assert!(point.distance(target) < 1e-4, "{eye} -> {target}");
+                assert!(on_face(local.x) && on_face(local.z), "{eye} -> {target} hit {}", hit.cell);
+            }
+        }
+    }
+
+    #[test]
+    fn raycast_from_a_whole_coordinate() {
+        let world = world_with(&[IVec3::new(2, 0, 0), IVec3::new(-2, 0, 0)]);
+        let origin = Vec3::new(0.0, 0.5, 0.5);
+
+        let hit = world.raycast(origin, Vec3::X, 10.0).unwrap();
+        assert_eq!((hit.cell, hit.normal, hit.distance), (IVec3::new(2, 0, 0), IVec3::NEG_X, 2.0));
+        let hit = world.raycast(origin, Vec3::NEG_X, 10.0).unwrap();
+        assert_eq!((hit.cell, hit.normal, hit.distance), (IVec3::new(-2, 0, 0), IVec3::X, 1.0));
+    }
+
+    #[test]
+    fn raycast_in_negative_coordinates() {
+        let world = world_with(&[IVec3::new(-3, -2, -5)]);
+        let hit = world.raycast(Vec3::new(-6.5, -1.5, -4.5), Vec3::X, 10.0).unwrap();
+        assert_eq!(hit.cell, IVec3::new(-3, -2, -5));
+        assert_eq!(hit.normal, IVec3::NEG_X);
+    }
+
+    #[test]
+    fn raycast_skips_the_cell_it_starts_in() {
+        let world = world_with(&[IVec3::ZERO, IVec3::new(0, 0, 2)]);
+        let hit = world.raycast(Vec3::splat(0.5), Vec3::Z, 10.0).unwrap();
+        assert_eq!(hit.cell, IVec3::new(0, 0, 2));
+    }
+
+    #[test]
+    fn raycast_below_passes_through_hidden_layers() {
+        let world = world_with(&[IVec3::new(0, 4, 0), IVec3::ZERO]);
+        let origin = Vec3::new(0.5, 8.5, 0.5);
+
+        assert_eq!(world.raycast_below(origin, Vec3::NEG_Y, 10.0, None).unwrap().cell.y, 4);
+        assert_eq!(world.raycast_below(origin, Vec3::NEG_Y, 10.0, Some(3)).unwrap().cell.y, 0);
+    }
+
+    // Vertices over every block type's mesh in the chunk
+    fn vertices(world: &VoxelWorld, chunk: IVec3, ceiling: Option<i32>) -> usize {
+        build_chunk_meshes(world, chunk, ceiling)
+            .values()
+            .map(Mesh::count_vertices)
+            .sum()
+    }
+
+    #[test]
+    fn flat_patch_emits_only_outer_faces() {
+        // Tops and bottoms of all 16 blocks plus the 4 sides of the rim,
+        // at 4 vertices each
+        let world = floor(IVec3::new(2, 3, 2), 4);
+        assert_eq!(vertices(&world, IVec3::ZERO, None), 4 * (2 * 16 + 4 * 4));
+    }
+
+    #[test]
+    fn faces_between_chunks_are_culled() {
+        // The same patch split over four chunks, one 2x2 corner in each
+        let world = floor(IVec3::new(-2, 3, -2), 4);
+        let chunks = [
+            IVec3::new(-1, 0, -1),
+            IVec3::new(0, 0, -1),
+            IVec3::new(-1, 0, 0),
+            IVec3::ZERO,
+        ];
+        for chunk in chunks {
+            assert_eq!(vertices(&world, chunk, None), 4 * (2 * 4 + 4));
+        }
+    }
+
+    #[test]
+    fn layers_above_the_ceiling_are_left_out() {
+        let mut world = floor(IVec3::new(2, 3, 2), 4);
+        for x in 2..6 {
+            for z in 2..6 {
+                world.set_block(IVec3::new(x, 4, z), BlockType::Stone);
+            }
+        }
+
+        assert_eq!(vertices(&world, IVec3::ZERO, None), 4 * (2 * 16 + 8 * 4));
+        assert_eq!(vertices(&world, IVec3::ZERO, Some(3)), 4 * (2 * 16 + 4 * 4));
+    }
+
+    #[test]
+    fn faces_against_glass_stay_visible() {
+        let mut world = world_with(&[IVec3::ZERO]);
+        world.set_block(IVec3::X, BlockType::Glass);
+        world.set_block(IVec3::new(2, 0, 0), BlockType::Glass);
+
+        let meshes = build_chunk_meshes(&world, IVec3::ZERO, None);
+        assert_eq!(meshes[&BlockType::Stone].count_vertices(), 6 * 4);
+        // Each pane hides its face against the other, and the first one
+        // also hides the face against the stone
+        assert_eq!(meshes[&BlockType::Glass].count_vertices(), (4 + 5) * 4);
+    }
+}